@@ -0,0 +1,229 @@
+//! Headless converter for scripts and build pipelines.
+//!
+//! `shift-cli convert *.webp --quality balanced --format mov --out dir` converts each input in
+//! turn with the same code the app uses. Progress goes to stderr and a JSON array with one
+//! result per input is printed to stdout.
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Mutex;
+
+use serde_json::{json, Map, Value};
+use tauri_app_lib::{BundledTools, ConvertOptions, Engine, LogDir, ProgressReporter, SystemRunner};
+
+/// Must match `identifier` in tauri.conf.json so the CLI shares the app's preset library.
+const APP_IDENTIFIER: &str = "com.justinthomas.shift";
+
+const USAGE: &str = "Usage: shift-cli convert [options] <input.webp>...
+
+Options:
+  --quality <high|balanced|small>   Quality preset
+  --format <mp4|mov|webm|gif>       Output format
+  --codec <name>                    Video codec, such as h264, hevc, vp9 or prores
+  --out <dir>                       Output directory (default: beside each input)
+  --preset <name>                   Saved preset from the app's library
+  --name <template>                 Output name template, such as {name}-{counter}
+  --fps <n>                         Output frame rate
+  --background <#rrggbb>            Background for outputs without alpha
+  --target-size <bytes>             Two-pass encode aiming at this file size
+  --max-width <px>, --max-height <px>
+  --options <json>                  Any other conversion options, as the app's JSON object;
+                                    the flags above take precedence
+  --ffmpeg <path>                   ffmpeg binary to use
+  --quiet                           No progress on stderr";
+
+/// Flags that map straight onto a conversion option, with whether the value is numeric.
+const OPTION_FLAGS: &[(&str, &str, bool)] = &[
+    ("--quality", "quality", false),
+    ("--format", "outputFormat", false),
+    ("--codec", "codec", false),
+    ("--out", "outputDir", false),
+    ("--preset", "preset", false),
+    ("--name", "outputNameTemplate", false),
+    ("--fps", "fps", true),
+    ("--background", "background", false),
+    ("--target-size", "targetSizeBytes", true),
+    ("--max-width", "maxWidth", true),
+    ("--max-height", "maxHeight", true),
+];
+
+struct Invocation {
+    inputs: Vec<String>,
+    options: Map<String, Value>,
+    ffmpeg: Option<PathBuf>,
+    quiet: bool,
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let invocation = match parse_args(&args) {
+        Ok(Some(invocation)) => invocation,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(err) => {
+            eprintln!("shift-cli: {}\n\n{}", err, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    let progress = StderrProgress {
+        quiet: invocation.quiet,
+        last_reported: Mutex::new(None),
+    };
+    let tools = BundledTools {
+        ffmpeg: invocation.ffmpeg,
+        resource_dir: resource_dir(),
+    };
+    let data_dir = dirs::data_dir().map(|dir| dir.join(APP_IDENTIFIER));
+    let logs = LogDir(data_dir.clone());
+    let engine = Engine {
+        progress: &progress,
+        tools: &tools,
+        logs: &logs,
+        runner: &SystemRunner,
+        presets: data_dir.map(|dir| dir.join("presets.json")),
+    };
+    let mut results = Vec::new();
+    let mut failed = false;
+    for (index, input) in invocation.inputs.iter().enumerate() {
+        // The sequence number feeds the {counter} token, as it does for a batch in the app.
+        let mut options = invocation.options.clone();
+        options
+            .entry("sequence")
+            .or_insert_with(|| json!(index + 1));
+        let options: ConvertOptions = match serde_json::from_value(Value::Object(options)) {
+            Ok(options) => options,
+            Err(err) => {
+                eprintln!("shift-cli: invalid options: {}", err);
+                return ExitCode::from(2);
+            }
+        };
+        match engine.convert_file(input, input, &options) {
+            Ok(result) => results.push(json!({ "input": input, "ok": true, "result": result })),
+            Err(err) => {
+                failed = true;
+                if !invocation.quiet {
+                    eprintln!("{}: {}", input, err);
+                    if let Some(log) = err.log_path() {
+                        eprintln!("{}: log written to {}", input, log.display());
+                    }
+                }
+                results.push(json!({ "input": input, "ok": false, "error": err }));
+            }
+        }
+    }
+
+    match serde_json::to_string_pretty(&results) {
+        Ok(json) => println!("{}", json),
+        Err(err) => {
+            eprintln!("shift-cli: failed to serialize results: {}", err);
+            return ExitCode::FAILURE;
+        }
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Returns `None` when only help was asked for.
+fn parse_args(args: &[String]) -> Result<Option<Invocation>, String> {
+    match args.first().map(String::as_str) {
+        None | Some("-h") | Some("--help") | Some("help") => return Ok(None),
+        Some("convert") => {}
+        Some(other) => return Err(format!("unknown command '{}'", other)),
+    }
+
+    let mut inputs = Vec::new();
+    let mut base = Map::new();
+    let mut flags = Map::new();
+    let mut ffmpeg = None;
+    let mut quiet = false;
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        let mut value = |flag: &str| {
+            rest.next()
+                .cloned()
+                .ok_or_else(|| format!("{} needs a value", flag))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--quiet" | "-q" => quiet = true,
+            "--ffmpeg" => ffmpeg = Some(PathBuf::from(value(arg)?)),
+            "--options" => match serde_json::from_str(&value(arg)?) {
+                Ok(Value::Object(map)) => base = map,
+                Ok(_) => return Err("--options must be a JSON object".to_string()),
+                Err(err) => return Err(format!("--options is not valid JSON: {}", err)),
+            },
+            flag if flag.starts_with("--") => {
+                let (_, key, numeric) = OPTION_FLAGS
+                    .iter()
+                    .find(|(name, _, _)| *name == flag)
+                    .ok_or_else(|| format!("unknown option '{}'", flag))?;
+                let raw = value(flag)?;
+                // Whole numbers stay integers so they fit integer options such as fps.
+                let parsed = if *numeric {
+                    match (raw.parse::<u64>(), raw.parse::<f64>()) {
+                        (Ok(number), _) => json!(number),
+                        (_, Ok(number)) => json!(number),
+                        _ => return Err(format!("{} expects a number, got '{}'", flag, raw)),
+                    }
+                } else {
+                    Value::String(raw)
+                };
+                flags.insert(key.to_string(), parsed);
+            }
+            input => inputs.push(input.to_string()),
+        }
+    }
+    if inputs.is_empty() {
+        return Err("no input files given".to_string());
+    }
+
+    base.extend(flags);
+    Ok(Some(Invocation {
+        inputs,
+        options: base,
+        ffmpeg,
+        quiet,
+    }))
+}
+
+struct StderrProgress {
+    quiet: bool,
+    /// Last update written, so repeated updates do not flood the terminal.
+    last_reported: Mutex<Option<(String, u8, String)>>,
+}
+
+impl ProgressReporter for StderrProgress {
+    fn report(&self, job_id: &str, progress: u8, stage: &str, eta_seconds: Option<u64>) {
+        if self.quiet {
+            return;
+        }
+        let report = (job_id.to_string(), progress, stage.to_string());
+        if let Ok(mut last) = self.last_reported.lock() {
+            if last.as_ref() == Some(&report) {
+                return;
+            }
+            *last = Some(report);
+        }
+        match eta_seconds {
+            Some(eta) => eprintln!("{}: {}% {} (about {}s left)", job_id, progress, stage, eta),
+            None => eprintln!("{}: {}% {}", job_id, progress, stage),
+        }
+    }
+}
+
+/// Bundled tools sit in Contents/Resources inside a macOS app bundle, and beside the
+/// executable elsewhere.
+fn resource_dir() -> Option<PathBuf> {
+    let exe_dir = std::env::current_exe().ok()?.parent()?.to_path_buf();
+    let bundle_resources = exe_dir.join("../Resources");
+    if bundle_resources.is_dir() {
+        Some(bundle_resources)
+    } else {
+        Some(exe_dir)
+    }
+}
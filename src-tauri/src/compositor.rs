@@ -0,0 +1,313 @@
+use std::borrow::Cow;
+
+use image::{Rgba, RgbaImage};
+
+use crate::webp::FrameInfo;
+
+/// Color the canvas starts as and frame rectangles are disposed to. Transparent is the
+/// default: browsers and ffmpeg both ignore the ANIM color, and libwebp usually writes opaque
+/// white there, which would hide alpha and the output background.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CanvasBackground {
+    /// The ANIM chunk's background color, as the container spec describes. Opt-in only.
+    Anim,
+    /// Transparent, ignoring the ANIM color the way browsers do.
+    Transparent,
+}
+
+impl CanvasBackground {
+    pub fn parse(value: Option<&str>) -> Result<Self, String> {
+        match value
+            .map(|background| background.trim().to_lowercase())
+            .as_deref()
+        {
+            None | Some("") | Some("transparent") => Ok(Self::Transparent),
+            Some("anim") => Ok(Self::Anim),
+            Some(other) => Err(format!(
+                "Unknown canvas background '{}'; expected anim or transparent",
+                other
+            )),
+        }
+    }
+
+    pub fn color(self, anim_color: Rgba<u8>) -> Rgba<u8> {
+        match self {
+            Self::Anim => anim_color,
+            Self::Transparent => Rgba([0, 0, 0, 0]),
+        }
+    }
+}
+
+/// Reconstructs animation frames the way the WebP container spec describes: the canvas starts
+/// as the background color, each frame is blended (or copied) into its rectangle, the canvas
+/// is shown, and only then is the frame's rectangle disposed to the background color.
+#[derive(Debug, Clone)]
+pub struct Compositor {
+    canvas: RgbaImage,
+    background: Rgba<u8>,
+    /// Opaque color shown through transparent pixels, for outputs that cannot keep alpha.
+    matte: Option<Rgba<u8>>,
+}
+
+impl Compositor {
+    pub fn new(width: u32, height: u32, background: Rgba<u8>, matte: Option<Rgba<u8>>) -> Self {
+        Self {
+            canvas: RgbaImage::from_pixel(width, height, background),
+            background,
+            matte,
+        }
+    }
+
+    /// Draws a decoded frame into its rectangle, clipped to the canvas.
+    pub fn draw(&mut self, frame: &FrameInfo, image: &RgbaImage) {
+        let (canvas_w, canvas_h) = self.canvas.dimensions();
+        for (x, y, src) in image.enumerate_pixels() {
+            let dst_x = frame.offset_x + x as usize;
+            let dst_y = frame.offset_y + y as usize;
+            if dst_x >= canvas_w as usize || dst_y >= canvas_h as usize {
+                continue;
+            }
+            let dst = self.canvas.get_pixel_mut(dst_x as u32, dst_y as u32);
+            *dst = if frame.blend { blend(*src, *dst) } else { *src };
+        }
+    }
+
+    /// The canvas as it is displayed, flattened onto the matte when there is one.
+    pub fn output(&self) -> Cow<'_, RgbaImage> {
+        match self.matte {
+            None => Cow::Borrowed(&self.canvas),
+            Some(matte) => {
+                let mut flat = self.canvas.clone();
+                for pixel in flat.pixels_mut() {
+                    *pixel = blend(*pixel, matte);
+                }
+                Cow::Owned(flat)
+            }
+        }
+    }
+
+    /// Applies the frame's disposal once it has been displayed.
+    pub fn dispose(&mut self, frame: &FrameInfo) {
+        if !frame.dispose_background {
+            return;
+        }
+        let (canvas_w, canvas_h) = self.canvas.dimensions();
+        let right = (frame.offset_x + frame.width).min(canvas_w as usize);
+        let bottom = (frame.offset_y + frame.height).min(canvas_h as usize);
+        for y in frame.offset_y..bottom {
+            for x in frame.offset_x..right {
+                self.canvas.put_pixel(x as u32, y as u32, self.background);
+            }
+        }
+    }
+}
+
+/// Non-premultiplied "source over" blending, using the formula from the WebP container spec.
+fn blend(src: Rgba<u8>, dst: Rgba<u8>) -> Rgba<u8> {
+    let src_a = src[3] as u32;
+    let dst_a = dst[3] as u32;
+    // dst.A * (1 - src.A / 255), kept scaled by 255.
+    let dst_weight = dst_a * (255 - src_a);
+    let alpha = src_a * 255 + dst_weight;
+    if alpha == 0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    let channel = |i: usize| {
+        let value = (src[i] as u32 * src_a * 255 + dst[i] as u32 * dst_weight + alpha / 2) / alpha;
+        value as u8
+    };
+    Rgba([
+        channel(0),
+        channel(1),
+        channel(2),
+        ((alpha + 127) / 255) as u8,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{animation, frame, lossy_frame, FLAG_DISPOSE, FLAG_NO_BLEND};
+    use crate::webp;
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const BLUE: Rgba<u8> = Rgba([0, 0, 255, 255]);
+    const GREEN: Rgba<u8> = Rgba([0, 255, 0, 255]);
+    const CLEAR: Rgba<u8> = Rgba([0, 0, 0, 0]);
+
+    /// Parses and composites `file`, returning every displayed canvas.
+    fn play(file: &[u8], background: Option<Rgba<u8>>, matte: Option<Rgba<u8>>) -> Vec<RgbaImage> {
+        let info = webp::parse(file).unwrap();
+        let mut compositor = Compositor::new(
+            info.canvas_width as u32,
+            info.canvas_height as u32,
+            background.unwrap_or(info.background_color),
+            matte,
+        );
+        let mut shown = Vec::new();
+        for frame in &info.frames {
+            compositor.draw(frame, &webp::decode_frame(file, frame).unwrap());
+            shown.push(compositor.output().into_owned());
+            compositor.dispose(frame);
+        }
+        shown
+    }
+
+    #[test]
+    fn canvas_starts_as_the_anim_background() {
+        let file = animation(4, 4, GREEN, &[frame(2, 2, RED, 0, 0, 0)]);
+        let shown = play(&file, None, None);
+        assert_eq!(*shown[0].get_pixel(0, 0), RED);
+        assert_eq!(*shown[0].get_pixel(3, 3), GREEN);
+    }
+
+    #[test]
+    fn disposal_happens_after_the_frame_is_shown() {
+        let file = animation(
+            4,
+            4,
+            CLEAR,
+            &[
+                frame(4, 4, RED, 0, 0, FLAG_DISPOSE),
+                frame(2, 2, BLUE, 2, 2, 0),
+            ],
+        );
+        let shown = play(&file, None, None);
+        assert!(shown[0].pixels().all(|pixel| *pixel == RED));
+        assert_eq!(*shown[1].get_pixel(0, 0), CLEAR);
+        assert_eq!(*shown[1].get_pixel(3, 3), BLUE);
+    }
+
+    #[test]
+    fn disposal_restores_the_background_color_only_inside_the_frame() {
+        let file = animation(
+            4,
+            4,
+            GREEN,
+            &[
+                frame(4, 4, RED, 0, 0, 0),
+                frame(2, 2, BLUE, 0, 0, FLAG_DISPOSE),
+                frame(2, 2, CLEAR, 2, 2, 0),
+            ],
+        );
+        let shown = play(&file, None, None);
+        assert_eq!(*shown[1].get_pixel(0, 0), BLUE);
+        assert_eq!(*shown[2].get_pixel(0, 0), GREEN);
+        assert_eq!(*shown[2].get_pixel(3, 0), RED);
+        assert_eq!(*shown[2].get_pixel(3, 3), RED);
+    }
+
+    #[test]
+    fn no_blend_frames_replace_pixels_including_alpha() {
+        let file = animation(
+            2,
+            2,
+            CLEAR,
+            &[
+                frame(2, 2, RED, 0, 0, 0),
+                frame(2, 2, CLEAR, 0, 0, FLAG_NO_BLEND),
+            ],
+        );
+        let shown = play(&file, None, None);
+        assert_eq!(*shown[1].get_pixel(1, 1), CLEAR);
+    }
+
+    #[test]
+    fn blended_frames_follow_the_spec_formula() {
+        let half_blue = Rgba([0, 0, 255, 128]);
+        let file = animation(
+            2,
+            2,
+            CLEAR,
+            &[frame(2, 2, RED, 0, 0, 0), frame(2, 2, half_blue, 0, 0, 0)],
+        );
+        let shown = play(&file, None, None);
+        assert_eq!(*shown[1].get_pixel(0, 0), Rgba([127, 0, 128, 255]));
+    }
+
+    #[test]
+    fn translucent_frames_blend_and_dispose_to_spec_values() {
+        let half_red = Rgba([255, 0, 0, 128]);
+        let half_blue = Rgba([0, 0, 255, 128]);
+        let file = animation(
+            4,
+            2,
+            CLEAR,
+            &[
+                frame(4, 2, half_blue, 0, 0, 0),
+                frame(2, 2, half_red, 0, 0, FLAG_DISPOSE),
+                frame(2, 2, half_red, 2, 0, 0),
+            ],
+        );
+        // A = 128 + 128 * (1 - 128/255) = 191.75
+        // R = 255 * 128 / 191.75 = 170.2
+        // B = 255 * 128 * (1 - 128/255) / 191.75 = 84.8
+        let mixed = Rgba([170, 0, 85, 192]);
+        let shown = play(&file, None, None);
+        assert_eq!(*shown[1].get_pixel(0, 0), mixed);
+        assert_eq!(*shown[1].get_pixel(3, 1), half_blue);
+        // The second frame's rectangle goes back to transparent, the rest stays.
+        assert_eq!(*shown[2].get_pixel(1, 1), CLEAR);
+        assert_eq!(*shown[2].get_pixel(3, 1), mixed);
+    }
+
+    #[test]
+    fn lossy_frames_with_alpha_are_placed_and_disposed() {
+        let lossy = lossy_frame(2, 2, FLAG_NO_BLEND | FLAG_DISPOSE);
+        let file = animation(20, 20, CLEAR, &[lossy, frame(2, 2, RED, 18, 18, 0)]);
+        let decoded = lossy_frame(0, 0, 0).image;
+        let shown = play(&file, None, None);
+        for (x, y, pixel) in decoded.enumerate_pixels() {
+            assert_eq!(shown[0].get_pixel(x + 2, y + 2), pixel, "{},{}", x, y);
+        }
+        assert_eq!(*shown[0].get_pixel(0, 0), CLEAR);
+        assert_eq!(*shown[1].get_pixel(9, 9), CLEAR);
+        assert_eq!(*shown[1].get_pixel(19, 19), RED);
+    }
+
+    #[test]
+    fn blending_onto_transparency_keeps_the_source_color() {
+        assert_eq!(blend(Rgba([10, 20, 30, 40]), CLEAR), Rgba([10, 20, 30, 40]));
+        assert_eq!(blend(CLEAR, CLEAR), CLEAR);
+    }
+
+    #[test]
+    fn frame_offsets_are_stored_halved() {
+        let file = animation(6, 6, CLEAR, &[frame(2, 2, RED, 4, 2, 0)]);
+        let shown = play(&file, None, None);
+        assert_eq!(*shown[0].get_pixel(4, 2), RED);
+        assert_eq!(*shown[0].get_pixel(3, 2), CLEAR);
+    }
+
+    #[test]
+    fn transparent_holes_are_flattened_onto_the_matte() {
+        let white = Rgba([255, 255, 255, 255]);
+        let file = animation(
+            2,
+            2,
+            CLEAR,
+            &[
+                frame(2, 2, RED, 0, 0, FLAG_DISPOSE),
+                frame(1, 1, BLUE, 0, 0, 0),
+            ],
+        );
+        let shown = play(&file, None, Some(white));
+        assert_eq!(*shown[1].get_pixel(0, 0), BLUE);
+        assert_eq!(*shown[1].get_pixel(1, 1), white);
+    }
+
+    #[test]
+    fn transparent_background_override_ignores_the_anim_color() {
+        let file = animation(
+            2,
+            2,
+            GREEN,
+            &[
+                frame(2, 2, RED, 0, 0, FLAG_DISPOSE),
+                frame(1, 1, BLUE, 0, 0, 0),
+            ],
+        );
+        let shown = play(&file, Some(CLEAR), None);
+        assert_eq!(*shown[1].get_pixel(1, 1), CLEAR);
+    }
+}
@@ -0,0 +1,420 @@
+use crate::engine::ConversionSettings;
+
+/// Dither algorithms accepted by ffmpeg's paletteuse filter.
+const GIF_DITHERS: &[&str] = &[
+    "bayer",
+    "heckbert",
+    "floyd_steinberg",
+    "sierra2",
+    "sierra2_4a",
+    "sierra3",
+    "burkes",
+    "atkinson",
+    "none",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Mp4,
+    Mov,
+    Gif,
+    Webm,
+}
+
+impl OutputFormat {
+    pub fn parse(value: &str) -> Self {
+        match value.trim().to_lowercase().as_str() {
+            "mov" => Self::Mov,
+            "gif" => Self::Gif,
+            "webm" => Self::Webm,
+            _ => Self::Mp4,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Mp4 => "mp4",
+            Self::Mov => "mov",
+            Self::Gif => "gif",
+            Self::Webm => "webm",
+        }
+    }
+
+    /// yuv420p video needs even frame dimensions; palette formats do not.
+    pub fn needs_even_dimensions(self) -> bool {
+        self != Self::Gif
+    }
+
+    /// Shortest per-frame delay worth emitting. GIF delays are centiseconds, and viewers
+    /// slow down anything under 2cs, so GIF frames are never split finer than 20 ms.
+    pub fn min_frame_tick_ms(self) -> u64 {
+        match self {
+            Self::Gif => 20,
+            _ => 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoCodec {
+    H264,
+    Hevc,
+    Vp9,
+    Av1,
+    ProRes4444,
+    Qtrle,
+}
+
+impl VideoCodec {
+    /// Picks the codec for `format`, defaulting per container and rejecting codecs the
+    /// container cannot hold. GIF output has its own encoder and ignores the codec.
+    pub fn resolve(value: Option<&str>, format: OutputFormat) -> Result<Self, String> {
+        let requested = value
+            .map(|codec| codec.trim().to_lowercase())
+            .filter(|codec| !codec.is_empty());
+        let codec = match requested.as_deref() {
+            None => return Ok(Self::default_for(format)),
+            Some("h264") | Some("avc") | Some("x264") => Self::H264,
+            Some("hevc") | Some("h265") | Some("x265") => Self::Hevc,
+            Some("vp9") => Self::Vp9,
+            Some("av1") => Self::Av1,
+            Some("prores") | Some("prores4444") => Self::ProRes4444,
+            Some("qtrle") | Some("animation") => Self::Qtrle,
+            Some(other) => return Err(format!("Unknown codec '{}'", other)),
+        };
+        if format == OutputFormat::Gif || codec.fits(format) {
+            Ok(codec)
+        } else {
+            Err(format!(
+                "Codec '{}' is not supported for {} output",
+                codec.name(),
+                format.extension()
+            ))
+        }
+    }
+
+    fn default_for(format: OutputFormat) -> Self {
+        match format {
+            OutputFormat::Webm => Self::Vp9,
+            _ => Self::H264,
+        }
+    }
+
+    fn fits(self, format: OutputFormat) -> bool {
+        match self {
+            Self::H264 | Self::Hevc => matches!(format, OutputFormat::Mp4 | OutputFormat::Mov),
+            Self::Vp9 | Self::Av1 => format == OutputFormat::Webm,
+            Self::ProRes4444 | Self::Qtrle => format == OutputFormat::Mov,
+        }
+    }
+
+    /// Codecs whose ffmpeg encoders can run a bitrate-targeted two-pass encode.
+    pub fn supports_two_pass(self) -> bool {
+        matches!(self, Self::H264 | Self::Hevc | Self::Vp9)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::H264 => "h264",
+            Self::Hevc => "hevc",
+            Self::Vp9 => "vp9",
+            Self::Av1 => "av1",
+            Self::ProRes4444 => "prores4444",
+            Self::Qtrle => "qtrle",
+        }
+    }
+
+    /// Maps the high/balanced/small quality presets onto the codec's own CRF scale and
+    /// speed setting (x264/x265 preset, libvpx cpu-used, or SVT-AV1 preset). ProRes uses the
+    /// first value as its qscale; QuickTime Animation is lossless and ignores both.
+    pub fn quality_params(self, quality: &str) -> (u8, String) {
+        let (crf, speed) = match (self, quality) {
+            (Self::H264, "balanced") => (18, "medium"),
+            (Self::H264, "small") => (24, "fast"),
+            (Self::H264, _) => (12, "slow"),
+            (Self::Hevc, "balanced") => (22, "medium"),
+            (Self::Hevc, "small") => (28, "fast"),
+            (Self::Hevc, _) => (16, "slow"),
+            (Self::Vp9, "balanced") => (31, "2"),
+            (Self::Vp9, "small") => (38, "4"),
+            (Self::Vp9, _) => (24, "1"),
+            (Self::Av1, "balanced") => (32, "6"),
+            (Self::Av1, "small") => (40, "8"),
+            (Self::Av1, _) => (24, "4"),
+            (Self::ProRes4444, "balanced") => (9, ""),
+            (Self::ProRes4444, "small") => (13, ""),
+            (Self::ProRes4444, _) => (4, ""),
+            (Self::Qtrle, _) => (0, ""),
+        };
+        (crf, speed.to_string())
+    }
+}
+
+pub fn parse_gif_dither(value: Option<&str>) -> Result<String, String> {
+    match value.map(|dither| dither.trim().to_lowercase()) {
+        None => Ok("sierra2_4a".to_string()),
+        Some(dither) if dither.is_empty() => Ok("sierra2_4a".to_string()),
+        Some(dither) if GIF_DITHERS.contains(&dither.as_str()) => Ok(dither),
+        Some(dither) => Err(format!(
+            "Unknown gif dither '{}'; expected one of: {}",
+            dither,
+            GIF_DITHERS.join(", ")
+        )),
+    }
+}
+
+/// One pass of a two-pass encode aimed at a target file size.
+pub struct TwoPass {
+    pub pass: u8,
+    pub bitrate_kbps: u64,
+    /// Path prefix for the encoder's pass statistics, shared by both passes.
+    pub log_prefix: String,
+}
+
+impl TwoPass {
+    fn is_first(&self) -> bool {
+        self.pass == 1
+    }
+}
+
+/// Average video bitrate that fits `duration_ms` of video into `target_bytes`, keeping 3%
+/// back for container overhead.
+pub fn target_bitrate_kbps(target_bytes: u64, duration_ms: u64) -> u64 {
+    let seconds = duration_ms.max(1) as f64 / 1000.0;
+    let kbps = target_bytes as f64 * 8.0 / 1000.0 / seconds * 0.97;
+    (kbps as u64).max(16)
+}
+
+/// Encoder and muxer arguments that follow the inputs, up to but not including the output
+/// path. `filter` is the video filter chain that prepares frames for the encoder.
+pub fn output_args(
+    settings: &ConversionSettings,
+    filter: &str,
+    loop_count: u16,
+    two_pass: Option<&TwoPass>,
+) -> Vec<String> {
+    match settings.output_format {
+        OutputFormat::Gif => vec![
+            "-an".into(),
+            "-vf".into(),
+            format!(
+                "{},split[s0][s1];[s0]palettegen=stats_mode=diff[p];[s1][p]paletteuse=dither={}:diff_mode=rectangle",
+                filter, settings.gif_dither
+            ),
+            "-loop".into(),
+            gif_loop_value(loop_count),
+        ],
+        _ => codec_args(settings, filter, two_pass),
+    }
+}
+
+/// Where a pass writes: the first pass of a two-pass encode only gathers statistics, so its
+/// output is discarded.
+pub fn destination_args(two_pass: Option<&TwoPass>, output_path: &str) -> Vec<String> {
+    match two_pass {
+        Some(two_pass) if two_pass.is_first() => vec!["-f".into(), "null".into(), "-".into()],
+        _ => vec!["-y".into(), output_path.into()],
+    }
+}
+
+fn codec_args(
+    settings: &ConversionSettings,
+    filter: &str,
+    two_pass: Option<&TwoPass>,
+) -> Vec<String> {
+    let tuning = &settings.tuning;
+    let rate = rate_args(settings, two_pass);
+    let keyframes = match tuning.keyframe_interval {
+        Some(interval) => vec!["-g".into(), interval.to_string()],
+        None => Vec::new(),
+    };
+    // The null muxer used by a first pass has no movflags.
+    let faststart: Vec<String> = match two_pass {
+        Some(two_pass) if two_pass.is_first() => Vec::new(),
+        _ => vec!["-movflags".into(), "+faststart".into()],
+    };
+    match settings.codec {
+        VideoCodec::Hevc => [
+            vec![
+                "-an".into(),
+                "-c:v".into(),
+                "libx265".into(),
+                "-pix_fmt".into(),
+                tuning.pix_fmt.clone(),
+            ],
+            flag("-profile:v", tuning.profile.as_ref()),
+            vec![
+                "-vf".into(),
+                filter.into(),
+                "-preset".into(),
+                tuning.preset.clone(),
+            ],
+            flag("-tune", tuning.tune.as_ref()),
+            rate,
+            keyframes,
+            vec![
+                "-x265-params".into(),
+                x265_params(settings, two_pass),
+                // hvc1 rather than hev1 so QuickTime and Safari will play the file.
+                "-tag:v".into(),
+                "hvc1".into(),
+            ],
+            faststart,
+        ]
+        .concat(),
+        VideoCodec::Vp9 => [
+            vec![
+                "-an".into(),
+                "-c:v".into(),
+                "libvpx-vp9".into(),
+                "-pix_fmt".into(),
+                tuning.pix_fmt.clone(),
+            ],
+            flag("-profile:v", tuning.profile.as_ref()),
+            vec!["-vf".into(), filter.into()],
+            rate,
+            keyframes,
+            flag("-tune-content", tuning.tune.as_ref()),
+            vec![
+                "-deadline".into(),
+                "good".into(),
+                "-cpu-used".into(),
+                tuning.preset.clone(),
+                "-row-mt".into(),
+                "1".into(),
+            ],
+        ]
+        .concat(),
+        VideoCodec::Av1 => [
+            vec![
+                "-an".into(),
+                "-c:v".into(),
+                "libsvtav1".into(),
+                "-pix_fmt".into(),
+                tuning.pix_fmt.clone(),
+                "-vf".into(),
+                filter.into(),
+            ],
+            rate,
+            vec!["-preset".into(), tuning.preset.clone()],
+            keyframes,
+        ]
+        .concat(),
+        VideoCodec::ProRes4444 => [
+            vec!["-an".into(), "-c:v".into(), "prores_ks".into()],
+            flag("-profile:v", tuning.profile.as_ref()),
+            vec![
+                "-pix_fmt".into(),
+                tuning.pix_fmt.clone(),
+                "-alpha_bits".into(),
+                "16".into(),
+                "-vendor".into(),
+                "apl0".into(),
+                "-vf".into(),
+                filter.into(),
+                "-qscale:v".into(),
+                tuning.crf.to_string(),
+            ],
+        ]
+        .concat(),
+        VideoCodec::Qtrle => [
+            vec![
+                "-an".into(),
+                "-c:v".into(),
+                "qtrle".into(),
+                "-pix_fmt".into(),
+                tuning.pix_fmt.clone(),
+                "-vf".into(),
+                filter.into(),
+            ],
+            keyframes,
+        ]
+        .concat(),
+        VideoCodec::H264 => [
+            vec![
+                "-an".into(),
+                "-c:v".into(),
+                "libx264".into(),
+                "-pix_fmt".into(),
+                tuning.pix_fmt.clone(),
+            ],
+            flag("-profile:v", tuning.profile.as_ref()),
+            flag("-level", tuning.level.as_ref()),
+            vec!["-vf".into(), filter.into()],
+            flag("-tune", tuning.tune.as_ref()),
+            vec!["-preset".into(), tuning.preset.clone()],
+            rate,
+            keyframes,
+            faststart,
+        ]
+        .concat(),
+    }
+}
+
+/// `[name, value]` when the setting is present, nothing otherwise.
+fn flag(name: &str, value: Option<&String>) -> Vec<String> {
+    match value {
+        Some(value) => vec![name.into(), value.clone()],
+        None => Vec::new(),
+    }
+}
+
+/// Constant-quality CRF by default; an average bitrate plus pass statistics when the encode
+/// targets a file size. libx265 reads its pass settings from x265-params instead.
+fn rate_args(settings: &ConversionSettings, two_pass: Option<&TwoPass>) -> Vec<String> {
+    let Some(two_pass) = two_pass else {
+        return match settings.codec {
+            // libvpx only runs in constant-quality mode when the bitrate cap is zero.
+            VideoCodec::Vp9 => vec![
+                "-crf".into(),
+                settings.tuning.crf.to_string(),
+                "-b:v".into(),
+                "0".into(),
+            ],
+            _ => vec!["-crf".into(), settings.tuning.crf.to_string()],
+        };
+    };
+    let bitrate = format!("{}k", two_pass.bitrate_kbps);
+    match settings.codec {
+        VideoCodec::Hevc => vec!["-b:v".into(), bitrate],
+        _ => vec![
+            "-b:v".into(),
+            bitrate,
+            "-pass".into(),
+            two_pass.pass.to_string(),
+            "-passlogfile".into(),
+            two_pass.log_prefix.clone(),
+        ],
+    }
+}
+
+/// libx265 has no ffmpeg options for its level or pass settings; they go through
+/// x265-params, where ':' separates options.
+fn x265_params(settings: &ConversionSettings, two_pass: Option<&TwoPass>) -> String {
+    let mut params = vec!["log-level=error".to_string()];
+    if let Some(level) = &settings.tuning.level {
+        params.push(format!("level-idc={}", level));
+    }
+    if let Some(two_pass) = two_pass {
+        params.push(format!("pass={}", two_pass.pass));
+        params.push(format!(
+            "stats={}",
+            escape_x265_value(&format!("{}.log", two_pass.log_prefix))
+        ));
+    }
+    params.join(":")
+}
+
+fn escape_x265_value(value: &str) -> String {
+    value.replace('\\', "/").replace(':', "\\:")
+}
+
+/// WebP counts total plays (0 = forever); the GIF muxer counts repeats after the first
+/// play and uses -1 for "play once".
+fn gif_loop_value(loop_count: u16) -> String {
+    match loop_count {
+        0 => "0".to_string(),
+        1 => "-1".to_string(),
+        count => (count - 1).to_string(),
+    }
+}
@@ -0,0 +1,1691 @@
+use std::fs;
+use std::io::{BufReader, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::JoinHandle;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use image::{Rgba, RgbaImage};
+use serde::Deserialize;
+use time::{format_description, OffsetDateTime};
+
+use crate::compositor::{CanvasBackground, Compositor};
+use crate::encode::{self, OutputFormat, VideoCodec};
+use crate::error::ConversionError;
+use crate::framing::{AspectFill, AspectRatio, CropRect};
+use crate::host::{LogStore, ProgressReporter, ToolLocator};
+use crate::jobs::{JobHandle, JobRegistry};
+use crate::presets;
+use crate::progress::{self, ProgressTotal};
+use crate::resize::Resize;
+use crate::runner::{ProcessRunner, ToolCommand};
+use crate::timing::{self, FrameDurations, Playback, TrimPoint};
+use crate::tuning::EncoderTuning;
+use crate::webp;
+
+/// The conversion pipeline, with the things it needs from its surroundings passed in. The
+/// desktop app and the command-line tool each build one over their own reporting, tool
+/// lookup and log storage.
+pub struct Engine<'a> {
+    pub progress: &'a dyn ProgressReporter,
+    pub tools: &'a dyn ToolLocator,
+    pub logs: &'a dyn LogStore,
+    pub runner: &'a dyn ProcessRunner,
+    /// Preset library that the `preset` option is looked up in.
+    pub presets: Option<PathBuf>,
+}
+
+impl Engine<'_> {
+    /// Converts one file as a job of its own. Nothing else can cancel it; use `convert_job`
+    /// for jobs held in a shared registry.
+    pub fn convert_file(
+        &self,
+        input_path: &str,
+        job_id: &str,
+        options: &ConvertOptions,
+    ) -> Result<ConversionResult, ConversionError> {
+        let jobs = JobRegistry::default();
+        let job = jobs.register(job_id);
+        self.convert_job(input_path, &job, options)
+    }
+
+    pub(crate) fn convert_job(
+        &self,
+        input_path: &str,
+        job: &JobHandle,
+        options: &ConvertOptions,
+    ) -> Result<ConversionResult, ConversionError> {
+        convert_registered_job(input_path, job, options, self)
+    }
+}
+
+fn convert_registered_job(
+    input_path: &str,
+    job: &JobHandle,
+    options: &ConvertOptions,
+    engine: &Engine,
+) -> Result<ConversionResult, ConversionError> {
+    let input = PathBuf::from(input_path);
+    
+    if !input.exists() {
+        return Err(ConversionError::InputMissing {
+            path: input_path.to_string(),
+        });
+    }
+
+    emit_progress(engine, &job.id, 0, "starting");
+
+    let options = match options.preset.as_deref().map(str::trim) {
+        Some(name) if !name.is_empty() => {
+            let library = engine
+                .presets
+                .as_deref()
+                .ok_or_else(|| {
+                    ConversionError::InvalidOptions("No preset library is available".to_string())
+                })?;
+            presets::find(library, name)?.apply_to(options)
+        }
+        _ => options.clone(),
+    };
+    let settings =
+        ConversionSettings::from_options(&options).map_err(ConversionError::InvalidOptions)?;
+
+    // Create output path (same directory or target directory, template-driven name)
+    let input_stem = input
+        .file_stem()
+        .ok_or_else(|| ConversionError::ProbeFailed("Invalid input file name".to_string()))?
+        .to_string_lossy()
+        .to_string();
+    let output_ext = settings.output_format.extension();
+    let output_stem = render_output_name(
+        &settings.output_name_template,
+        &input_stem,
+        settings.sequence,
+        output_ext,
+    );
+    let output = match &settings.output_dir {
+        Some(dir) => {
+            let mut out_dir = PathBuf::from(dir);
+            fs::create_dir_all(&out_dir)
+                .map_err(|e| ConversionError::output("Failed to create output directory", &e))?;
+            out_dir.push(&output_stem);
+            out_dir.set_extension(output_ext);
+            out_dir
+        }
+        None => {
+            let mut out = input.with_file_name(&output_stem);
+            out.set_extension(output_ext);
+            out
+        }
+    };
+    let output = ensure_unique_path(output);
+    let output_str = output.to_string_lossy().to_string();
+
+    let ffmpeg_path = match engine.tools.ffmpeg_path() {
+        Ok(path) => path,
+        Err(err) => {
+            let err = ConversionError::ToolNotFound {
+                message: format!("Failed to locate ffmpeg: {}", err),
+                log_path: None,
+            };
+            return Err(with_debug_report(engine, input_path, None, err));
+        }
+    };
+
+    let info = webp::read_info(&input).map_err(ConversionError::ProbeFailed)?;
+
+    // A cancel can land between the queue starting the job and this point; nothing has been
+    // written yet, so stop before ffmpeg is spawned.
+    job.check_cancelled()?;
+
+    // Timing that filters on the decoded stream cannot reproduce goes straight to the
+    // frame-by-frame path, which writes every frame for exactly its planned duration.
+    let direct = if direct_timing_holds(&info, &settings) {
+        run_ffmpeg_conversion(
+            engine,
+            job,
+            &ffmpeg_path,
+            input_path,
+            &output_str,
+            &info,
+            &settings,
+        )
+    } else {
+        Err(ConversionError::encoder(
+            "Frame timing needs the frame-by-frame conversion",
+        ))
+    };
+    if let Err(err) = direct {
+        if job.is_cancelled() {
+            return Err(finish_cancelled(engine, job, &output));
+        }
+        // Only an ffmpeg that cannot read the file is worth retrying frame by frame.
+        let ConversionError::EncoderFailed { message, .. } = err else {
+            return Err(with_debug_report(engine, input_path, Some(&ffmpeg_path), err));
+        };
+        if let Err(fallback_err) = fallback_convert_frames(
+            engine,
+            job,
+            &ffmpeg_path,
+            input_path,
+            &output,
+            &settings,
+        )
+        {
+            if job.is_cancelled() {
+                return Err(finish_cancelled(engine, job, &output));
+            }
+            let combined = format!("{}\n{}", message, fallback_err);
+            let err = match fallback_err {
+                ConversionError::EncoderFailed { stderr, .. } => ConversionError::EncoderFailed {
+                    message: combined,
+                    stderr,
+                    log_path: None,
+                },
+                ConversionError::DiskFull { .. } => ConversionError::DiskFull {
+                    message: combined,
+                    log_path: None,
+                },
+                _ => ConversionError::FallbackFailed {
+                    message: combined,
+                    log_path: None,
+                },
+            };
+            return Err(with_debug_report(engine, input_path, Some(&ffmpeg_path), err));
+        }
+    }
+
+    let size_bytes = fs::metadata(&output)
+        .map(|metadata| metadata.len())
+        .map_err(|e| ConversionError::output("Failed to read converted file", &e))?;
+    emit_progress(engine, &job.id, 100, "done");
+    Ok(ConversionResult {
+        output_path: output_str,
+        size_bytes,
+        target_size_bytes: settings.target_size_bytes,
+        target_met: settings.target_size_bytes.map(|target| size_bytes <= target),
+    })
+}
+
+/// Writes a failure report for errors that keep one and records where it went.
+fn with_debug_report(
+    engine: &Engine,
+    input_path: &str,
+    ffmpeg_path: Option<&PathBuf>,
+    err: ConversionError,
+) -> ConversionError {
+    if !err.keeps_log() {
+        return err;
+    }
+    let report = build_debug_report(input_path, ffmpeg_path, &err.to_string());
+    let log_path = engine.logs.write(&report);
+    err.with_log_path(log_path)
+}
+
+/// Removes whatever partial output a cancelled job left behind and reports the final stage.
+fn finish_cancelled(engine: &Engine, job: &JobHandle, output: &Path) -> ConversionError {
+    let _ = fs::remove_file(output);
+    emit_progress(engine, &job.id, 0, "cancelled");
+    ConversionError::Cancelled
+}
+
+fn run_ffmpeg_conversion(
+    engine: &Engine,
+    job: &JobHandle,
+    ffmpeg_path: &PathBuf,
+    input_path: &str,
+    output_path: &str,
+    info: &webp::WebpInfo,
+    settings: &ConversionSettings,
+) -> Result<(), ConversionError> {
+    let clip = plan_clip(info, settings).map_err(ConversionError::InvalidOptions)?;
+    let plays = clip.plays;
+    let total = if !info.animated {
+        ProgressTotal::DurationMs((settings.static_duration * 1000.0) as u64)
+    } else if settings.fps.is_some() {
+        ProgressTotal::Frames(clip.frames.len() as u64 * plays as u64)
+    } else {
+        ProgressTotal::DurationMs(clip.duration_ms(None) * plays as u64)
+    };
+
+    // Trim, frame timing, speed and playback order, applied to the decoded stream before
+    // anything else.
+    let mut timeline = Vec::new();
+    let mut reordered = false;
+    if info.animated {
+        if clip.range != (0..info.frames.len()) {
+            timeline.push(format!(
+                "trim=start_frame={}:end_frame={},setpts=PTS-STARTPTS",
+                clip.range.start, clip.range.end
+            ));
+            reordered = true;
+        }
+        // An fps override replaces the source timing at the input.
+        if settings.fps.is_none() {
+            timeline.extend(settings.frame_durations.filter());
+        }
+        if settings.speed != 1.0 {
+            timeline.push(format!("setpts=PTS/{}", settings.speed));
+        }
+        if let Some(playback) = settings.playback.filter(clip.range.len()) {
+            timeline.push(playback);
+            reordered = true;
+        }
+    }
+    // Untouched clips loop at the input. Trimmed or reordered clips have to be cut before
+    // they repeat, so they loop in the filter graph instead.
+    if plays > 1 && reordered {
+        timeline.push(format!(
+            "loop=loop={}:size={}:start=0",
+            plays - 1,
+            clip.frames.len()
+        ));
+    }
+
+    let mut input_args: Vec<String> = Vec::new();
+    if plays > 1 && !reordered {
+        input_args.extend(["-stream_loop".to_string(), (plays - 1).to_string()]);
+    }
+    if !info.animated {
+        // Static WebP -> short video clip.
+        let fps = settings.fps.unwrap_or(30);
+        input_args.extend([
+            "-loop".to_string(),
+            "1".to_string(),
+            "-t".to_string(),
+            settings.static_duration.to_string(),
+            "-r".to_string(),
+            fps.to_string(),
+        ]);
+    } else if let Some(fps) = settings.fps {
+        input_args.extend(["-r".to_string(), fps.to_string()]);
+    }
+    input_args.extend(["-i".to_string(), input_path.to_string()]);
+
+    timeline.push(
+        build_ffmpeg_filter(settings, info.canvas_width, info.canvas_height)
+            .map_err(ConversionError::InvalidOptions)?,
+    );
+    let vf = timeline.join(",");
+
+    let duration_ms = clip.duration_ms(settings.fps) * plays as u64;
+    let loop_count = output_loop_count(info, settings);
+    run_passes(settings, duration_ms, |two_pass, span| {
+        let command = ToolCommand::new(ffmpeg_path)
+            .args(["-hide_banner", "-loglevel", "error", "-progress", "pipe:1", "-nostats"])
+            .args(&input_args)
+            .args(encode::output_args(settings, &vf, loop_count, two_pass))
+            .args(encode::destination_args(two_pass, output_path));
+        run_encoder(engine, job, &command, "FFmpeg conversion", total, span, |_| Ok(()))
+    })
+}
+
+/// Whether the direct path's timeline filters give every frame its planned duration.
+/// ffmpeg's reverse filter keeps the forward timestamps, so frames only come out with the
+/// right durations when they all last the same. setpts moves frames but cannot lengthen the
+/// last decoded one, so its stored duration has to survive normalization whatever order the
+/// frames are played in.
+fn direct_timing_holds(info: &webp::WebpInfo, settings: &ConversionSettings) -> bool {
+    // Invalid options are reported by the direct path itself.
+    let Ok(clip) = plan_clip(info, settings) else {
+        return true;
+    };
+    if !info.animated || settings.fps.is_some() {
+        return true;
+    }
+    let reordered = settings.playback.filter(clip.range.len()).is_some();
+    if reordered && clip.frames.windows(2).any(|pair| pair[0].1 != pair[1].1) {
+        return false;
+    }
+    if clip.range.is_empty() {
+        return true;
+    }
+    let stored_ms = info.frames[clip.range.end - 1].duration_ms;
+    settings.frame_durations.normalize(stored_ms) == stored_ms
+}
+
+fn fallback_convert_frames(
+    engine: &Engine,
+    job: &JobHandle,
+    ffmpeg_path: &PathBuf,
+    input_path: &str,
+    output_path: &Path,
+    settings: &ConversionSettings,
+) -> Result<(), ConversionError> {
+    let data = fs::read(input_path)
+        .map_err(|e| ConversionError::ProbeFailed(format!("Failed to read input: {}", e)))?;
+    let info = webp::parse(&data).map_err(ConversionError::ProbeFailed)?;
+    let (canvas_w, canvas_h, frames) = (info.canvas_width, info.canvas_height, &info.frames);
+    let vf = frame_filter(settings, canvas_w, canvas_h).map_err(ConversionError::InvalidOptions)?;
+
+    let clip = plan_clip(&info, settings).map_err(ConversionError::InvalidOptions)?;
+    let plays = clip.plays;
+    let durations: Vec<u64> = clip.frames.iter().map(|(_, duration_ms)| *duration_ms).collect();
+    let timing = timing::plan_frame_timing(
+        &durations,
+        settings.fps,
+        settings.output_format.min_frame_tick_ms(),
+    );
+    let sequence: Vec<(usize, usize)> = clip
+        .frames
+        .iter()
+        .map(|(index, _)| *index)
+        .zip(timing.repeats.iter().copied())
+        .collect();
+
+    let output_str = output_path
+        .to_str()
+        .ok_or_else(|| ConversionError::OutputFailed("Invalid output path".to_string()))?;
+    let total = ProgressTotal::Frames(timing.repeats.iter().sum::<usize>() as u64 * plays as u64);
+    let compositor = fallback_compositor(&info, settings);
+    let duration_ms = clip.duration_ms(settings.fps) * plays as u64;
+    let loop_count = output_loop_count(&info, settings);
+
+    run_passes(settings, duration_ms, |two_pass, span| {
+        let command = ToolCommand::new(ffmpeg_path)
+            .args([
+                "-hide_banner",
+                "-loglevel",
+                "error",
+                "-progress",
+                "pipe:1",
+                "-nostats",
+                "-f",
+                "rawvideo",
+                "-pix_fmt",
+                "rgba",
+                "-s",
+                &format!("{}x{}", canvas_w, canvas_h),
+                "-framerate",
+                &timing.rate,
+                "-i",
+                "pipe:0",
+            ])
+            .args(encode::output_args(settings, &vf, loop_count, two_pass))
+            .args(["-vsync", if settings.fps.is_some() { "cfr" } else { "vfr" }])
+            .args(encode::destination_args(two_pass, output_str))
+            .piped_stdin();
+
+        run_encoder(engine, job, &command, "Fallback FFmpeg", total, span, |stdin| {
+            let mut stdin = stdin.ok_or_else(|| ConversionError::encoder("Failed to open FFmpeg stdin"))?;
+            stream_composited_frames(job, &data, frames, &sequence, plays, &compositor, &mut stdin)
+        })
+    })
+}
+
+/// The canvas starts transparent unless the ANIM color was asked for. Outputs without alpha
+/// see transparent pixels over the chosen background, never black, and only when flattened.
+fn fallback_compositor(info: &webp::WebpInfo, settings: &ConversionSettings) -> Compositor {
+    Compositor::new(
+        info.canvas_width as u32,
+        info.canvas_height as u32,
+        settings.canvas_background.color(info.background_color),
+        (!settings.tuning.keeps_alpha()).then(|| settings.background_rgba()),
+    )
+}
+
+/// Runs the encode once, or twice when the settings target a file size. `run_pass` gets the
+/// pass settings and the slice of the overall progress bar the pass reports into.
+fn run_passes(
+    settings: &ConversionSettings,
+    duration_ms: u64,
+    mut run_pass: impl FnMut(Option<&encode::TwoPass>, (u8, u8)) -> Result<(), ConversionError>,
+) -> Result<(), ConversionError> {
+    let Some(target_bytes) = settings.target_size_bytes else {
+        return run_pass(None, (0, 100));
+    };
+    // The pass statistics live next to nothing else, and go away with the directory.
+    let log_dir = TempDir::create("webpconv-2pass").map_err(ConversionError::OutputFailed)?;
+    let log_prefix = log_dir.path().join("ffmpeg2pass").to_string_lossy().to_string();
+    let bitrate_kbps = encode::target_bitrate_kbps(target_bytes, duration_ms);
+    for (pass, span) in [(1, (0, 50)), (2, (50, 100))] {
+        let two_pass = encode::TwoPass {
+            pass,
+            bitrate_kbps,
+            log_prefix: log_prefix.clone(),
+        };
+        run_pass(Some(&two_pass), span)?;
+    }
+    Ok(())
+}
+
+/// Runs one ffmpeg pass through the engine's runner and waits for it, forwarding progress
+/// into `span`. `feed` receives the process's stdin, if it was piped, and stdin is closed as
+/// soon as `feed` returns. Errors are prefixed with `label`.
+fn run_encoder(
+    engine: &Engine,
+    job: &JobHandle,
+    command: &ToolCommand,
+    label: &str,
+    total: ProgressTotal,
+    span: (u8, u8),
+    feed: impl FnOnce(Option<Box<dyn Write + Send>>) -> Result<(), ConversionError>,
+) -> Result<(), ConversionError> {
+    let mut child = engine.runner.spawn(command).map_err(|e| {
+        let message = format!("Failed to execute FFmpeg: {}", e);
+        if e.kind() == std::io::ErrorKind::NotFound {
+            ConversionError::ToolNotFound {
+                message,
+                log_path: None,
+            }
+        } else {
+            ConversionError::encoder(message)
+        }
+    })?;
+    let pid = child.id();
+    if let Some(pid) = pid {
+        job.attach(pid);
+    }
+
+    let stdout = child.take_stdout();
+    let stderr_reader = read_pipe_in_background(child.take_stderr());
+    let (fed, status) = std::thread::scope(|scope| {
+        let progress_reader =
+            scope.spawn(|| report_ffmpeg_progress(engine.progress, &job.id, stdout, total, span));
+        let fed = feed(child.take_stdin());
+        if fed.is_err() {
+            child.kill();
+        }
+        // Detach before reaping, so a cancel cannot kill whatever reuses the pid.
+        child.wait_for_exit();
+        if let Some(pid) = pid {
+            job.detach(pid);
+        }
+        let status = child.wait();
+        let _ = progress_reader.join();
+        (fed, status)
+    });
+    let stderr = stderr_reader.join().unwrap_or_default();
+    let stderr = stderr.trim();
+
+    if let Err(err) = fed {
+        // A write to stdin fails when ffmpeg has exited, and its stderr says why.
+        return Err(match err {
+            ConversionError::EncoderFailed { message, .. } => ConversionError::EncoderFailed {
+                message: if stderr.is_empty() {
+                    message
+                } else {
+                    format!("{}\n{}: {}", message, label, stderr)
+                },
+                stderr: stderr.to_string(),
+                log_path: None,
+            },
+            other => other,
+        });
+    }
+    let succeeded = status
+        .map_err(|e| ConversionError::encoder(format!("Failed to wait for FFmpeg: {}", e)))?;
+    if !succeeded {
+        let message = if stderr.is_empty() {
+            format!("{} failed", label)
+        } else {
+            format!("{} failed: {}", label, stderr)
+        };
+        return Err(if stderr.contains("No space left on device") {
+            ConversionError::DiskFull {
+                message,
+                log_path: None,
+            }
+        } else {
+            ConversionError::EncoderFailed {
+                message,
+                stderr: stderr.to_string(),
+                log_path: None,
+            }
+        });
+    }
+    Ok(())
+}
+
+/// One pass through the clip as it will play: source frame indices in playback order with
+/// their on-screen durations, after trim and speed.
+struct Clip {
+    frames: Vec<(usize, u64)>,
+    /// Source frames kept by the trim.
+    range: Range<usize>,
+    /// Times the pass repeats in a video output.
+    plays: u32,
+}
+
+impl Clip {
+    /// Playing time of one pass. With an fps override every frame lasts one output frame.
+    fn duration_ms(&self, fps: Option<u32>) -> u64 {
+        match fps {
+            Some(fps) if self.frames.len() > 1 => {
+                self.frames.len() as u64 * 1000 / fps.max(1) as u64
+            }
+            _ => self.frames.iter().map(|(_, duration_ms)| duration_ms).sum(),
+        }
+    }
+}
+
+/// Applies frame timing, trim, speed, playback order and repeats to the source frames. A still image is a
+/// single frame lasting the static duration. GIFs loop by themselves, so they play once.
+fn plan_clip(info: &webp::WebpInfo, settings: &ConversionSettings) -> Result<Clip, String> {
+    if !info.animated || info.frames.len() == 1 {
+        return Ok(Clip {
+            frames: vec![(0, (settings.static_duration * 1000.0) as u64)],
+            range: 0..1,
+            plays: 1,
+        });
+    }
+    let durations: Vec<u64> = info
+        .frames
+        .iter()
+        .map(|frame| settings.frame_durations.normalize(frame.duration_ms))
+        .collect();
+    let range = timing::trim_range(&durations, settings.trim_start, settings.trim_end)?;
+    let frames = settings
+        .playback
+        .frame_order(range.len())
+        .into_iter()
+        .map(|offset| {
+            let index = range.start + offset;
+            (index, timing::scale_duration(durations[index], settings.speed))
+        })
+        .collect();
+    let mut clip = Clip {
+        frames,
+        range,
+        plays: 1,
+    };
+    if settings.output_format != OutputFormat::Gif {
+        clip.plays = timing::play_count(
+            settings.loops,
+            settings.use_source_loops,
+            info.loop_count,
+            clip.duration_ms(settings.fps),
+            settings.min_duration_ms,
+        );
+    }
+    Ok(clip)
+}
+
+/// Loop count written to formats that store one (GIF). An explicit `loops` overrides the
+/// source's own count.
+fn output_loop_count(info: &webp::WebpInfo, settings: &ConversionSettings) -> u16 {
+    match settings.loops {
+        Some(loops) if !settings.use_source_loops => loops.min(u16::MAX as u32) as u16,
+        _ => info.loop_count,
+    }
+}
+
+/// Writes `plays` passes over `sequence` to `sink`. Each entry is a source frame index and how
+/// many times that frame is written; frames left out are still composited but never written.
+/// Every pass starts from a copy of `compositor`.
+fn stream_composited_frames(
+    job: &JobHandle,
+    data: &[u8],
+    frames: &[webp::FrameInfo],
+    sequence: &[(usize, usize)],
+    plays: u32,
+    compositor: &Compositor,
+    sink: &mut impl Write,
+) -> Result<(), ConversionError> {
+    if sequence.windows(2).all(|pair| pair[0].0 < pair[1].0) {
+        // In file order, so each frame can be written as soon as it is composited.
+        let mut repeats = vec![0; frames.len()];
+        for &(index, repeat) in sequence {
+            repeats[index] = repeat;
+        }
+        for _ in 0..plays {
+            // Each loop of the animation starts again from an empty canvas.
+            composite_frames(job, data, frames, &mut compositor.clone(), |index, canvas| {
+                write_frame(sink, canvas, index, repeats[index])
+            })?;
+        }
+    } else {
+        // Frames only composite correctly front to back, so out-of-order playback keeps
+        // every composited frame it needs before writing any.
+        let mut composited: Vec<Option<RgbaImage>> = vec![None; frames.len()];
+        for &(index, _) in sequence {
+            composited[index] = Some(RgbaImage::new(0, 0));
+        }
+        composite_frames(job, data, frames, &mut compositor.clone(), |index, canvas| {
+            if let Some(slot) = composited[index].as_mut() {
+                *slot = canvas.clone();
+            }
+            Ok(())
+        })?;
+        for _ in 0..plays {
+            for &(index, repeat) in sequence {
+                job.check_cancelled()?;
+                if let Some(canvas) = &composited[index] {
+                    write_frame(sink, canvas, index, repeat)?;
+                }
+            }
+        }
+    }
+    sink.flush()
+        .map_err(|e| ConversionError::encoder(format!("Failed to stream frames to FFmpeg: {}", e)))
+}
+
+/// Decodes and composites each frame in file order, handing the displayed canvas to `emit`
+/// once the frame is drawn and before its disposal.
+fn composite_frames(
+    job: &JobHandle,
+    data: &[u8],
+    frames: &[webp::FrameInfo],
+    compositor: &mut Compositor,
+    mut emit: impl FnMut(usize, &RgbaImage) -> Result<(), ConversionError>,
+) -> Result<(), ConversionError> {
+    for (index, frame) in frames.iter().enumerate() {
+        job.check_cancelled()?;
+        let frame_img = webp::decode_frame(data, frame)
+            .map_err(|e| ConversionError::FallbackFailed {
+                message: format!("Failed to decode frame {}: {}", index + 1, e),
+                log_path: None,
+            })?;
+        compositor.draw(frame, &frame_img);
+        emit(index, &compositor.output())?;
+        compositor.dispose(frame);
+    }
+    Ok(())
+}
+
+fn write_frame(
+    sink: &mut impl Write,
+    canvas: &RgbaImage,
+    index: usize,
+    repeat: usize,
+) -> Result<(), ConversionError> {
+    for _ in 0..repeat {
+        sink.write_all(canvas.as_raw()).map_err(|e| {
+            ConversionError::encoder(format!("Failed to stream frame {} to FFmpeg: {}", index + 1, e))
+        })?;
+    }
+    Ok(())
+}
+
+/// Forwards ffmpeg's progress as job progress, scaled into `span` of the overall bar, until
+/// ffmpeg closes its stdout.
+fn report_ffmpeg_progress(
+    progress: &dyn ProgressReporter,
+    job_id: &str,
+    stdout: Option<Box<dyn Read + Send>>,
+    total: ProgressTotal,
+    span: (u8, u8),
+) {
+    if let Some(stdout) = stdout {
+        progress::watch_ffmpeg_progress(BufReader::new(stdout), total, |update| {
+            let (start, end) = span;
+            let percent = start + (update.percent as u16 * (end - start) as u16 / 100) as u8;
+            progress.report(job_id, percent, "encoding", update.eta_seconds);
+        });
+    }
+}
+
+fn read_pipe_in_background(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<String> {
+    std::thread::spawn(move || {
+        let mut contents = String::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_string(&mut contents);
+        }
+        contents
+    })
+}
+
+fn build_debug_report(
+    input_path: &str,
+    ffmpeg_path: Option<&PathBuf>,
+    error: &str,
+) -> String {
+    let resource_dir = format!("{:?}", std::env::var("TAURI_RESOURCE_DIR").ok());
+    let mut report = String::new();
+    report.push_str("WebP conversion failure report\n");
+    report.push_str(&format!("Input: {}\n", input_path));
+    report.push_str(&format!("Arch: {}\n", std::env::consts::ARCH));
+    report.push_str(&format!("TAURI_RESOURCE_DIR: {}\n", resource_dir));
+    if let Some(path) = ffmpeg_path {
+        report.push_str(&format!("ffmpeg: {} (exists: {})\n", path.display(), path.exists()));
+    }
+    report.push_str(&format!("Error:\n{}\n", error));
+    report
+}
+
+fn emit_progress(engine: &Engine, job_id: &str, progress: u8, stage: &str) {
+    engine.progress.report(job_id, progress, stage, None);
+}
+
+/// What a finished conversion produced. `target_met` is only set for target-size encodes.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversionResult {
+    pub(crate) output_path: String,
+    pub(crate) size_bytes: u64,
+    target_size_bytes: Option<u64>,
+    pub(crate) target_met: Option<bool>,
+}
+
+/// Options for one conversion, as the frontend sends them. Every field is optional.
+#[derive(Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConvertOptions {
+    /// Name of a saved preset whose values fill in the options left unset here.
+    preset: Option<String>,
+    output_dir: Option<String>,
+    pub(crate) quality: Option<String>,
+    pub(crate) fps: Option<u32>,
+    pub(crate) background: Option<String>,
+    pub(crate) output_format: Option<String>,
+    pub(crate) codec: Option<String>,
+    gif_dither: Option<String>,
+    pub(crate) output_name_template: Option<String>,
+    sequence: Option<u32>,
+    pub(crate) static_duration: Option<f64>,
+    pub(crate) target_size_bytes: Option<u64>,
+    pub(crate) advanced: Option<AdvancedOptions>,
+    max_width: Option<u32>,
+    max_height: Option<u32>,
+    scale: Option<f64>,
+    fit: Option<String>,
+    scale_algorithm: Option<String>,
+    crop: Option<CropRect>,
+    aspect: Option<String>,
+    aspect_fill: Option<String>,
+    loops: Option<u32>,
+    use_source_loops: Option<bool>,
+    min_duration: Option<f64>,
+    playback: Option<String>,
+    canvas_background: Option<String>,
+    frame_timing: Option<String>,
+    min_frame_ms: Option<u64>,
+    trim_start_ms: Option<u64>,
+    trim_end_ms: Option<u64>,
+    trim_start_frame: Option<usize>,
+    trim_end_frame: Option<usize>,
+    speed: Option<f64>,
+}
+
+/// Explicit encoder settings that override the quality preset. Each field is checked against
+/// the chosen codec.
+#[derive(Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AdvancedOptions {
+    pub(crate) crf: Option<u8>,
+    pub(crate) preset: Option<String>,
+    pub(crate) profile: Option<String>,
+    pub(crate) level: Option<String>,
+    pub(crate) tune: Option<String>,
+    pub(crate) keyframe_interval: Option<u32>,
+    pub(crate) pix_fmt: Option<String>,
+}
+
+impl AdvancedOptions {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.crf.is_none()
+            && self.preset.is_none()
+            && self.profile.is_none()
+            && self.level.is_none()
+            && self.tune.is_none()
+            && self.keyframe_interval.is_none()
+            && self.pix_fmt.is_none()
+    }
+}
+
+pub(crate) struct ConversionSettings {
+    output_dir: Option<String>,
+    pub(crate) codec: VideoCodec,
+    pub(crate) tuning: EncoderTuning,
+    fps: Option<u32>,
+    background: Option<String>,
+    pub(crate) output_format: OutputFormat,
+    pub(crate) gif_dither: String,
+    output_name_template: String,
+    sequence: u32,
+    static_duration: f64,
+    target_size_bytes: Option<u64>,
+    resize: Resize,
+    crop: Option<CropRect>,
+    aspect: Option<AspectRatio>,
+    aspect_fill: AspectFill,
+    loops: Option<u32>,
+    use_source_loops: bool,
+    min_duration_ms: u64,
+    playback: Playback,
+    canvas_background: CanvasBackground,
+    frame_durations: FrameDurations,
+    trim_start: Option<TrimPoint>,
+    trim_end: Option<TrimPoint>,
+    speed: f64,
+}
+
+impl ConversionSettings {
+    pub(crate) fn from_options(options: &ConvertOptions) -> Result<Self, String> {
+        let output_dir = options
+            .output_dir
+            .as_deref()
+            .and_then(|dir| {
+                let trimmed = dir.trim();
+                if trimmed.is_empty() {
+                    None
+                } else {
+                    Some(trimmed.to_string())
+                }
+            });
+        let quality = options
+            .quality
+            .as_deref()
+            .unwrap_or("high")
+            .to_lowercase();
+        let output_format = OutputFormat::parse(options.output_format.as_deref().unwrap_or("mp4"));
+        let codec = VideoCodec::resolve(options.codec.as_deref(), output_format)?;
+        let has_advanced = options
+            .advanced
+            .as_ref()
+            .is_some_and(|advanced| !advanced.is_empty());
+        if output_format == OutputFormat::Gif && has_advanced {
+            return Err("Advanced encoder settings are not supported for gif output".to_string());
+        }
+        let tuning = EncoderTuning::resolve(codec, &quality, options.advanced.as_ref())?;
+        let gif_dither = encode::parse_gif_dither(options.gif_dither.as_deref())?;
+        let output_name_template = options
+            .output_name_template
+            .as_deref()
+            .unwrap_or("{name}")
+            .trim()
+            .to_string();
+        let sequence = options.sequence.unwrap_or(1);
+        let static_duration = options.static_duration.unwrap_or(1.0);
+        let static_duration = if static_duration.is_finite() {
+            static_duration
+        } else {
+            1.0
+        }
+        .clamp(0.1, 60.0);
+        let target_size_bytes = options.target_size_bytes.filter(|bytes| *bytes > 0);
+        if target_size_bytes.is_some() {
+            if output_format == OutputFormat::Gif {
+                return Err("Target file size is not supported for gif output".to_string());
+            }
+            if !codec.supports_two_pass() {
+                return Err(format!(
+                    "Target file size is not supported with the {} codec",
+                    codec.name()
+                ));
+            }
+            if options.advanced.as_ref().is_some_and(|advanced| advanced.crf.is_some()) {
+                return Err("CRF cannot be combined with a target file size".to_string());
+            }
+        }
+        let resize = Resize::parse(
+            options.max_width,
+            options.max_height,
+            options.scale,
+            options.fit.as_deref(),
+            options.scale_algorithm.as_deref(),
+        )?;
+        let use_source_loops = options.use_source_loops.unwrap_or(false);
+        if options.loops == Some(0) {
+            return Err("Loops must be at least 1".to_string());
+        }
+        if use_source_loops && options.loops.is_some() {
+            return Err("Use either loops or the source loop count, not both".to_string());
+        }
+        let min_duration = options.min_duration.unwrap_or(0.0);
+        if !min_duration.is_finite() || !(0.0..=3600.0).contains(&min_duration) {
+            return Err(format!(
+                "Minimum duration {} is out of range (0 to 3600 seconds)",
+                min_duration
+            ));
+        }
+        let speed = options.speed.unwrap_or(1.0);
+        if !speed.is_finite() || !(0.1..=10.0).contains(&speed) {
+            return Err(format!("Speed {} is out of range (0.1 to 10)", speed));
+        }
+        if speed != 1.0 && options.fps.is_some() {
+            return Err(
+                "Speed cannot be combined with an fps override, which replaces source timing"
+                    .to_string(),
+            );
+        }
+        Ok(Self {
+            output_dir,
+            codec,
+            tuning,
+            fps: options.fps,
+            background: options.background.clone(),
+            output_format,
+            gif_dither,
+            output_name_template,
+            sequence,
+            static_duration,
+            target_size_bytes,
+            resize,
+            crop: options.crop,
+            aspect: AspectRatio::parse(options.aspect.as_deref())?,
+            aspect_fill: AspectFill::parse(options.aspect_fill.as_deref())?,
+            loops: options.loops,
+            use_source_loops,
+            min_duration_ms: (min_duration * 1000.0).round() as u64,
+            playback: Playback::parse(options.playback.as_deref())?,
+            canvas_background: CanvasBackground::parse(options.canvas_background.as_deref())?,
+            frame_durations: FrameDurations::parse(
+                options.frame_timing.as_deref(),
+                options.min_frame_ms,
+            )?,
+            trim_start: trim_point("start", options.trim_start_ms, options.trim_start_frame)?,
+            trim_end: trim_point("end", options.trim_end_ms, options.trim_end_frame)?,
+            speed,
+        })
+    }
+
+    /// Initial canvas fill for the frame compositor. Outputs that keep alpha start from
+    /// transparent pixels and ignore the background option.
+    fn canvas_fill(&self) -> Rgba<u8> {
+        if self.tuning.keeps_alpha() {
+            Rgba([0, 0, 0, 0])
+        } else {
+            self.background_rgba()
+        }
+    }
+
+    fn background_rgba(&self) -> Rgba<u8> {
+        if let Some(color) = &self.background {
+            if let Some(rgba) = parse_hex_color(color) {
+                return rgba;
+            }
+        }
+        Rgba([255, 255, 255, 255])
+    }
+}
+
+fn trim_point(
+    side: &str,
+    ms: Option<u64>,
+    frame: Option<usize>,
+) -> Result<Option<TrimPoint>, String> {
+    match (ms, frame) {
+        (Some(_), Some(_)) => Err(format!(
+            "Trim {} can be given in milliseconds or as a frame index, not both",
+            side
+        )),
+        (Some(ms), None) => Ok(Some(TrimPoint::Ms(ms))),
+        (None, Some(frame)) => Ok(Some(TrimPoint::Frame(frame))),
+        (None, None) => Ok(None),
+    }
+}
+
+fn parse_hex_color(color: &str) -> Option<Rgba<u8>> {
+    let trimmed = color.trim().trim_start_matches('#');
+    match trimmed.len() {
+        6 => {
+            let r = u8::from_str_radix(&trimmed[0..2], 16).ok()?;
+            let g = u8::from_str_radix(&trimmed[2..4], 16).ok()?;
+            let b = u8::from_str_radix(&trimmed[4..6], 16).ok()?;
+            Some(Rgba([r, g, b, 255]))
+        }
+        8 => {
+            let r = u8::from_str_radix(&trimmed[0..2], 16).ok()?;
+            let g = u8::from_str_radix(&trimmed[2..4], 16).ok()?;
+            let b = u8::from_str_radix(&trimmed[4..6], 16).ok()?;
+            let a = u8::from_str_radix(&trimmed[6..8], 16).ok()?;
+            Some(Rgba([r, g, b, a]))
+        }
+        _ => None,
+    }
+}
+
+fn build_ffmpeg_filter(
+    settings: &ConversionSettings,
+    canvas_w: usize,
+    canvas_h: usize,
+) -> Result<String, String> {
+    let framing = frame_filter(settings, canvas_w, canvas_h)?;
+    if settings.tuning.keeps_alpha() {
+        return Ok(format!("format=rgba,{}", framing));
+    }
+    // Flatten transparency onto the background, as the frame fallback does with its canvas.
+    let Rgba([r, g, b, a]) = settings.background_rgba();
+    Ok(format!(
+        "format=rgba,split[fg][bg];[bg]lutrgb=r={}:g={}:b={}:a={}[base];[base][fg]overlay=format=auto,{}",
+        r, g, b, a, framing
+    ))
+}
+
+/// Crop, aspect canvas and resize steps taking a `canvas_w` x `canvas_h` frame to the output
+/// size. Shared by the direct path and the frame fallback so both produce the same frame.
+fn frame_filter(
+    settings: &ConversionSettings,
+    canvas_w: usize,
+    canvas_h: usize,
+) -> Result<String, String> {
+    let mut steps = Vec::new();
+    let (mut w, mut h) = (canvas_w, canvas_h);
+    let fill_color = fill_color(settings);
+
+    if let Some(crop) = &settings.crop {
+        crop.check(w, h)?;
+        steps.push(crop.filter());
+        (w, h) = (crop.width as usize, crop.height as usize);
+    }
+    if let Some(aspect) = settings.aspect {
+        let (aspect_w, aspect_h) = aspect.canvas_for(w, h);
+        if (aspect_w, aspect_h) != (w, h) {
+            steps.push(settings.aspect_fill.filter(w, h, aspect_w, aspect_h, &fill_color));
+            (w, h) = (aspect_w, aspect_h);
+        }
+    }
+    let plan = settings
+        .resize
+        .plan(w, h, settings.output_format.needs_even_dimensions());
+    if let Some(geometry) = plan.filter(&settings.resize.algorithm, &fill_color) {
+        steps.push(geometry);
+    }
+
+    Ok(if steps.is_empty() {
+        "null".to_string()
+    } else {
+        steps.join(",")
+    })
+}
+
+/// ffmpeg color for padding: the compositor's canvas fill, so transparent for outputs with alpha
+/// and `background_rgba` otherwise.
+fn fill_color(settings: &ConversionSettings) -> String {
+    let Rgba([r, g, b, a]) = settings.canvas_fill();
+    format!("0x{:02X}{:02X}{:02X}{:02X}", r, g, b, a)
+}
+
+fn render_output_name(template: &str, input_stem: &str, sequence: u32, ext: &str) -> String {
+    let (date, time) = format_date_time();
+    let counter = sequence.to_string();
+    let mut name = template.to_string();
+    name = replace_token(&name, "name", input_stem);
+    name = replace_token(&name, "counter", &counter);
+    name = replace_token(&name, "date", &date);
+    name = replace_token(&name, "time", &time);
+    name = replace_token(&name, "ext", ext);
+    name = sanitize_filename(name.trim());
+    name = strip_trailing_extension(&name, ext);
+    if name.is_empty() {
+        sanitize_filename(input_stem)
+    } else {
+        name
+    }
+}
+
+fn sanitize_filename(value: &str) -> String {
+    let mut sanitized = String::new();
+    for ch in value.chars() {
+        match ch {
+            '/' | '\\' | ':' => sanitized.push('-'),
+            _ => sanitized.push(ch),
+        }
+    }
+    sanitized.trim().to_string()
+}
+
+fn replace_token(source: &str, token: &str, value: &str) -> String {
+    let brace = format!("{{{}}}", token);
+    let bracket = format!("[{}]", token);
+    source.replace(&brace, value).replace(&bracket, value)
+}
+
+fn strip_trailing_extension(value: &str, ext: &str) -> String {
+    if ext.is_empty() {
+        return value.to_string();
+    }
+    let lower = value.to_lowercase();
+    let suffix = format!(".{}", ext.to_lowercase());
+    if lower.ends_with(&suffix) && value.len() >= suffix.len() {
+        let new_len = value.len() - suffix.len();
+        return value[..new_len].to_string();
+    }
+    value.to_string()
+}
+
+fn format_date_time() -> (String, String) {
+    let now = OffsetDateTime::now_utc();
+    let date_format = format_description::parse("[year][month][day]").unwrap();
+    let time_format = format_description::parse("[hour][minute][second]").unwrap();
+    let date = now
+        .format(&date_format)
+        .unwrap_or_else(|_| "00000000".to_string());
+    let time = now
+        .format(&time_format)
+        .unwrap_or_else(|_| "000000".to_string());
+    (date, time)
+}
+
+fn ensure_unique_path(path: PathBuf) -> PathBuf {
+    if !path.exists() {
+        return path;
+    }
+    let ext = path
+        .extension()
+        .and_then(|value| value.to_str())
+        .unwrap_or("")
+        .to_string();
+    let stem = path
+        .file_stem()
+        .and_then(|value| value.to_str())
+        .unwrap_or("output")
+        .to_string();
+    let parent = path.parent().map(|p| p.to_path_buf()).unwrap_or_default();
+    let mut index = 1;
+    loop {
+        let candidate_name = format!("{}-{}", stem, index);
+        let mut candidate = parent.join(&candidate_name);
+        if !ext.is_empty() {
+            candidate.set_extension(&ext);
+        }
+        if !candidate.exists() {
+            return candidate;
+        }
+        index += 1;
+    }
+}
+
+/// Scratch directory that is removed with everything in it when dropped.
+pub(crate) struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub(crate) fn create(prefix: &str) -> Result<Self, String> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis())
+            .unwrap_or(0);
+        let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!(
+            "{}-{}-{}-{}",
+            prefix,
+            std::process::id(),
+            stamp,
+            count
+        ));
+        fs::create_dir_all(&path)
+            .map_err(|e| format!("Failed to create temp directory: {}", e))?;
+        Ok(Self { path })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{animation, frame, still};
+    use crate::host::{BundledTools, LogDir};
+    use crate::recording::RecordingRunner;
+    use serde_json::json;
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const BLUE: Rgba<u8> = Rgba([0, 0, 255, 128]);
+
+    struct Silent;
+
+    impl ProgressReporter for Silent {
+        fn report(&self, _: &str, _: u8, _: &str, _: Option<u64>) {}
+    }
+
+    /// A 4x2 animation of three 100 ms frames.
+    fn clip() -> Vec<u8> {
+        animation(
+            4,
+            2,
+            RED,
+            &[
+                frame(4, 2, RED, 0, 0, 0),
+                frame(2, 2, BLUE, 2, 0, 0),
+                frame(4, 2, BLUE, 0, 0, 0),
+            ],
+        )
+    }
+
+    /// Converts `file`, saved as clip.webp in a fresh directory, through `runner`.
+    fn convert(
+        runner: &RecordingRunner,
+        file: &[u8],
+        options: serde_json::Value,
+    ) -> (TempDir, Result<ConversionResult, ConversionError>) {
+        let dir = TempDir::create("webpconv-test").unwrap();
+        let input = dir.path().join("clip.webp");
+        fs::write(&input, file).unwrap();
+        let engine = Engine {
+            progress: &Silent,
+            tools: &BundledTools {
+                ffmpeg: Some(PathBuf::from("ffmpeg")),
+                resource_dir: None,
+            },
+            logs: &LogDir(Some(dir.path().to_path_buf())),
+            runner,
+            presets: None,
+        };
+        let options = serde_json::from_value(options).unwrap();
+        let result = engine.convert_file(input.to_str().unwrap(), "test", &options);
+        (dir, result)
+    }
+
+    const PROGRESS_ARGS: &[&str] = &[
+        "-hide_banner",
+        "-loglevel",
+        "error",
+        "-progress",
+        "pipe:1",
+        "-nostats",
+    ];
+    const BROWSER_TIMING: &str = "setpts='if(eq(N,0),PTS,PREV_OUTPTS+if(lte(PTS-PREV_INPTS,0.01/TB),0.1/TB,PTS-PREV_INPTS))'";
+    const ON_WHITE: &str = "format=rgba,split[fg][bg];[bg]lutrgb=r=255:g=255:b=255:a=255[base];[base][fg]overlay=format=auto,null";
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn path(dir: &TempDir, name: &str) -> String {
+        dir.path().join(name).to_string_lossy().to_string()
+    }
+
+    /// The default high-quality H.264 encoder arguments around `filter`, ending with `rate`.
+    fn h264(filter: &str, rate: &[&str]) -> Vec<String> {
+        let mut args = strings(&[
+            "-an",
+            "-c:v",
+            "libx264",
+            "-pix_fmt",
+            "yuv420p",
+            "-profile:v",
+            "high",
+            "-level",
+            "4.1",
+            "-vf",
+            filter,
+            "-tune",
+            "animation",
+            "-preset",
+            "slow",
+        ]);
+        args.extend(strings(rate));
+        args
+    }
+
+    /// A direct ffmpeg call reading the WebP with `input_args`, encoding H.264 through
+    /// `filter` into clip.mp4.
+    fn direct_h264(dir: &TempDir, input_args: &[&str], filter: &str) -> Vec<String> {
+        let mut args = strings(PROGRESS_ARGS);
+        args.extend(strings(input_args));
+        args.extend(["-i".to_string(), path(dir, "clip.webp")]);
+        args.extend(h264(filter, &["-crf", "12", "-movflags", "+faststart"]));
+        args.extend(["-y".to_string(), path(dir, "clip.mp4")]);
+        args
+    }
+
+    #[test]
+    fn default_settings_encode_h264_over_white() {
+        let runner = RecordingRunner::new();
+        let (dir, result) = convert(&runner, &clip(), json!({}));
+        let result = result.unwrap();
+        assert_eq!(result.output_path, path(&dir, "clip.mp4"));
+        assert_eq!(result.size_bytes, 0);
+        assert_eq!(
+            runner.args(),
+            [direct_h264(&dir, &[], &format!("{},{}", BROWSER_TIMING, ON_WHITE))]
+        );
+    }
+
+    #[test]
+    fn alpha_codecs_skip_the_background() {
+        let runner = RecordingRunner::new();
+        let (dir, result) = convert(
+            &runner,
+            &clip(),
+            json!({ "outputFormat": "mov", "codec": "prores" }),
+        );
+        result.unwrap();
+        let mut expected = strings(PROGRESS_ARGS);
+        expected.extend(["-i".to_string(), path(&dir, "clip.webp")]);
+        expected.extend(strings(&[
+            "-an",
+            "-c:v",
+            "prores_ks",
+            "-profile:v",
+            "4444",
+            "-pix_fmt",
+            "yuva444p10le",
+            "-alpha_bits",
+            "16",
+            "-vendor",
+            "apl0",
+            "-vf",
+            &format!("{},format=rgba,null", BROWSER_TIMING),
+            "-qscale:v",
+            "4",
+            "-y",
+        ]));
+        expected.push(path(&dir, "clip.mov"));
+        assert_eq!(runner.args(), [expected]);
+    }
+
+    #[test]
+    fn webm_keeps_alpha_unless_the_pix_fmt_drops_it() {
+        let runner = RecordingRunner::new();
+        let (dir, result) = convert(&runner, &clip(), json!({ "outputFormat": "webm" }));
+        result.unwrap();
+        let mut expected = strings(PROGRESS_ARGS);
+        expected.extend(["-i".to_string(), path(&dir, "clip.webp")]);
+        expected.extend(strings(&[
+            "-an",
+            "-c:v",
+            "libvpx-vp9",
+            "-pix_fmt",
+            "yuva420p",
+            "-vf",
+            &format!("{},format=rgba,null", BROWSER_TIMING),
+            "-crf",
+            "24",
+            "-b:v",
+            "0",
+            "-deadline",
+            "good",
+            "-cpu-used",
+            "1",
+            "-row-mt",
+            "1",
+            "-y",
+        ]));
+        expected.push(path(&dir, "clip.webm"));
+        assert_eq!(runner.args(), [expected]);
+
+        let runner = RecordingRunner::new();
+        let (_dir, result) = convert(
+            &runner,
+            &clip(),
+            json!({ "outputFormat": "webm", "advanced": { "pixFmt": "yuv420p10le" } }),
+        );
+        result.unwrap();
+        let args = &runner.args()[0];
+        let filter = &args[args.iter().position(|arg| arg == "-vf").unwrap() + 1];
+        assert!(filter.contains(ON_WHITE), "{}", filter);
+    }
+
+    #[test]
+    fn gif_output_builds_a_palette_and_stores_the_loop_count() {
+        let runner = RecordingRunner::new();
+        let (dir, result) = convert(&runner, &clip(), json!({ "outputFormat": "gif", "loops": 2 }));
+        result.unwrap();
+        let mut expected = strings(PROGRESS_ARGS);
+        expected.extend(["-i".to_string(), path(&dir, "clip.webp")]);
+        expected.extend(strings(&[
+            "-an",
+            "-vf",
+            &format!(
+                "{},{},split[s0][s1];[s0]palettegen=stats_mode=diff[p];[s1][p]paletteuse=dither=sierra2_4a:diff_mode=rectangle",
+                BROWSER_TIMING, ON_WHITE
+            ),
+            "-loop",
+            "1",
+            "-y",
+        ]));
+        expected.push(path(&dir, "clip.gif"));
+        assert_eq!(runner.args(), [expected]);
+    }
+
+    #[test]
+    fn target_size_runs_a_discarded_first_pass() {
+        let runner = RecordingRunner::new();
+        let (dir, result) = convert(&runner, &clip(), json!({ "targetSizeBytes": 100000 }));
+        assert_eq!(result.unwrap().target_met, Some(true));
+        let calls = runner.args();
+        assert_eq!(calls.len(), 2);
+        let log_prefix = &calls[0][calls[0].len() - 4];
+        assert!(log_prefix.ends_with("ffmpeg2pass"), "{}", log_prefix);
+
+        let filter = format!("{},{}", BROWSER_TIMING, ON_WHITE);
+        let pass = |number: &str, destination: &[&str]| {
+            let mut args = strings(PROGRESS_ARGS);
+            args.extend(["-i".to_string(), path(&dir, "clip.webp")]);
+            args.extend(h264(
+                &filter,
+                &["-b:v", "2586k", "-pass", number, "-passlogfile", log_prefix],
+            ));
+            args.extend(strings(destination));
+            args
+        };
+        let mut second = pass("2", &["-movflags", "+faststart", "-y"]);
+        second.push(path(&dir, "clip.mp4"));
+        assert_eq!(calls, [pass("1", &["-f", "null", "-"]), second]);
+    }
+
+    #[test]
+    fn plain_loops_repeat_the_input() {
+        let runner = RecordingRunner::new();
+        let (dir, result) = convert(&runner, &clip(), json!({ "loops": 3 }));
+        result.unwrap();
+        assert_eq!(
+            runner.args(),
+            [direct_h264(
+                &dir,
+                &["-stream_loop", "2"],
+                &format!("{},{}", BROWSER_TIMING, ON_WHITE)
+            )]
+        );
+    }
+
+    #[test]
+    fn reordered_clips_loop_in_the_filter_graph() {
+        let runner = RecordingRunner::new();
+        let (dir, result) = convert(&runner, &clip(), json!({ "playback": "reverse", "loops": 2 }));
+        result.unwrap();
+        assert_eq!(
+            runner.args(),
+            [direct_h264(
+                &dir,
+                &[],
+                &format!("{},reverse,loop=loop=1:size=3:start=0,{}", BROWSER_TIMING, ON_WHITE)
+            )]
+        );
+    }
+
+    #[test]
+    fn unevenly_timed_clips_reverse_frame_by_frame() {
+        let runner = RecordingRunner::new();
+        let mut short = frame(4, 2, BLUE, 0, 0, 0);
+        short.duration_ms = 40;
+        let file = animation(4, 2, RED, &[frame(4, 2, RED, 0, 0, 0), short]);
+        let (dir, result) = convert(&runner, &file, json!({ "playback": "reverse" }));
+        result.unwrap();
+        let calls = runner.calls();
+        assert_eq!(calls.len(), 1);
+        let mut expected = strings(PROGRESS_ARGS);
+        expected.extend(strings(&[
+            "-f",
+            "rawvideo",
+            "-pix_fmt",
+            "rgba",
+            "-s",
+            "4x2",
+            "-framerate",
+            "1000/20",
+        ]));
+        assert_eq!(calls[0].command.args[..expected.len()], expected);
+        assert_eq!(calls[0].command.args.last(), Some(&path(&dir, "clip.mp4")));
+        // The 40 ms frame first for two 20 ms ticks, then the 100 ms frame for five.
+        assert_eq!(calls[0].stdin_bytes, (2 + 5) * 4 * 2 * 4);
+    }
+
+    #[test]
+    fn a_last_frame_that_needs_normalizing_is_timed_frame_by_frame() {
+        let runner = RecordingRunner::new();
+        let mut instant = frame(4, 2, BLUE, 0, 0, 0);
+        instant.duration_ms = 0;
+        let file = animation(4, 2, RED, &[frame(4, 2, RED, 0, 0, 0), instant]);
+        let (_dir, result) = convert(&runner, &file, json!({}));
+        result.unwrap();
+        let calls = runner.calls();
+        assert_eq!(calls.len(), 1);
+        assert!(calls[0].command.stdin);
+        // Browsers show the 0 ms frame for 100 ms, one tick like the first frame.
+        let rate = calls[0].command.args.iter().position(|arg| arg == "-framerate");
+        assert_eq!(calls[0].command.args[rate.unwrap() + 1], "1000/100");
+        assert_eq!(calls[0].stdin_bytes, 2 * 4 * 2 * 4);
+
+        // Left as stored, the same clip keeps to the direct path.
+        let runner = RecordingRunner::new();
+        let (_dir, result) = convert(&runner, &file, json!({ "frameTiming": "raw" }));
+        result.unwrap();
+        assert!(!runner.calls()[0].command.stdin);
+    }
+
+    #[test]
+    fn a_short_last_source_frame_is_timed_frame_by_frame_in_any_order() {
+        let mut short = frame(4, 2, BLUE, 0, 0, 0);
+        short.duration_ms = 5;
+        let reverse = animation(4, 2, RED, &[frame(4, 2, RED, 0, 0, 0), short]);
+        let mut short = frame(4, 2, BLUE, 0, 0, 0);
+        short.duration_ms = 5;
+        let ping_pong = animation(
+            4,
+            2,
+            RED,
+            &[frame(4, 2, RED, 0, 0, 0), frame(4, 2, BLUE, 0, 0, 0), short],
+        );
+        for (file, playback) in [(reverse, "reverse"), (ping_pong, "ping-pong")] {
+            let runner = RecordingRunner::new();
+            let (_dir, result) = convert(&runner, &file, json!({ "playback": playback }));
+            result.unwrap();
+            let calls = runner.calls();
+            assert_eq!(calls.len(), 1, "{}", playback);
+            assert!(calls[0].command.stdin, "{}", playback);
+        }
+    }
+
+    #[test]
+    fn trim_cuts_before_timing_and_speed_after() {
+        let runner = RecordingRunner::new();
+        let (dir, result) = convert(
+            &runner,
+            &clip(),
+            json!({ "trimStartFrame": 1, "speed": 2.0 }),
+        );
+        result.unwrap();
+        assert_eq!(
+            runner.args(),
+            [direct_h264(
+                &dir,
+                &[],
+                &format!(
+                    "trim=start_frame=1:end_frame=3,setpts=PTS-STARTPTS,{},setpts=PTS/2,{}",
+                    BROWSER_TIMING, ON_WHITE
+                )
+            )]
+        );
+    }
+
+    #[test]
+    fn still_images_become_a_fixed_length_clip() {
+        let runner = RecordingRunner::new();
+        let file = still(&RgbaImage::from_pixel(4, 2, RED));
+        let (dir, result) = convert(&runner, &file, json!({ "staticDuration": 2.0, "fps": 24 }));
+        result.unwrap();
+        assert_eq!(
+            runner.args(),
+            [direct_h264(&dir, &["-loop", "1", "-t", "2", "-r", "24"], ON_WHITE)]
+        );
+    }
+
+    #[test]
+    fn failed_encodes_fall_back_to_streamed_frames() {
+        let runner = RecordingRunner::failing("boom");
+        let (dir, result) = convert(&runner, &clip(), json!({}));
+        let err = result.unwrap_err();
+        let log_path = err.log_path().unwrap().to_path_buf();
+        assert!(log_path.starts_with(dir.path()));
+        assert_eq!(
+            serde_json::to_value(&err).unwrap(),
+            json!({
+                "code": "encoder_failed",
+                "message": "FFmpeg conversion failed: boom\nFallback FFmpeg failed: boom",
+                "logPath": log_path,
+                "stderr": "boom",
+            })
+        );
+        assert!(fs::read_to_string(&log_path).unwrap().contains("Fallback FFmpeg failed: boom"));
+
+        let calls = runner.calls();
+        assert_eq!(calls.len(), 2);
+        assert!(!calls[0].command.stdin);
+        let fallback = &calls[1];
+        let mut expected = strings(PROGRESS_ARGS);
+        expected.extend(strings(&[
+            "-f",
+            "rawvideo",
+            "-pix_fmt",
+            "rgba",
+            "-s",
+            "4x2",
+            "-framerate",
+            "1000/100",
+            "-i",
+            "pipe:0",
+        ]));
+        expected.extend(h264(
+            "null",
+            &["-crf", "12", "-movflags", "+faststart", "-vsync", "vfr", "-y"],
+        ));
+        expected.push(path(&dir, "clip.mp4"));
+        assert_eq!(fallback.command.args, expected);
+        assert!(fallback.command.stdin);
+        // Three composited 4x2 RGBA frames.
+        assert_eq!(fallback.stdin_bytes, 3 * 4 * 2 * 4);
+    }
+
+    #[test]
+    fn unknown_gif_dithers_are_rejected() {
+        let runner = RecordingRunner::new();
+        let (_dir, result) = convert(
+            &runner,
+            &clip(),
+            json!({ "outputFormat": "gif", "gifDither": "ordered" }),
+        );
+        assert_eq!(result.unwrap_err().code(), "invalid_options");
+        assert!(runner.args().is_empty());
+    }
+
+    #[test]
+    fn alpha_codecs_keep_alpha_over_an_opaque_anim_color() {
+        let white = Rgba([255, 255, 255, 255]);
+        let file = animation(4, 2, white, &[frame(2, 2, RED, 0, 0, 0)]);
+        let info = webp::parse(&file).unwrap();
+        let options: ConvertOptions =
+            serde_json::from_value(json!({ "outputFormat": "mov", "codec": "prores" })).unwrap();
+        let settings = ConversionSettings::from_options(&options).unwrap();
+
+        let mut compositor = fallback_compositor(&info, &settings);
+        compositor.draw(&info.frames[0], &webp::decode_frame(&file, &info.frames[0]).unwrap());
+        let shown = compositor.output();
+        assert_eq!(*shown.get_pixel(0, 0), RED);
+        assert_eq!(*shown.get_pixel(3, 1), Rgba([0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn a_full_disk_is_not_retried_frame_by_frame() {
+        let runner = RecordingRunner::failing("clip.mp4: No space left on device");
+        let (_dir, result) = convert(&runner, &clip(), json!({}));
+        let err = result.unwrap_err();
+        assert_eq!(err.code(), "disk_full");
+        assert!(err.log_path().is_some());
+        assert_eq!(runner.calls().len(), 1);
+    }
+
+    #[test]
+    fn invalid_options_never_reach_ffmpeg() {
+        let runner = RecordingRunner::new();
+        let (_dir, result) = convert(&runner, &clip(), json!({ "trimStartFrame": 5 }));
+        let err = result.unwrap_err();
+        assert_eq!(err.code(), "invalid_options");
+        assert_eq!(err.log_path(), None);
+        assert!(runner.calls().is_empty());
+    }
+}
@@ -0,0 +1,151 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+/// Why a conversion failed. Serializes as `{ code, message, logPath, stderr }` so the UI can
+/// pick guidance and retry actions by `code` rather than by parsing the message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConversionError {
+    InputMissing {
+        path: String,
+    },
+    /// The options, or the preset they name, cannot be used for this input.
+    InvalidOptions(String),
+    ToolNotFound {
+        message: String,
+        log_path: Option<PathBuf>,
+    },
+    /// The input could not be read or parsed as WebP.
+    ProbeFailed(String),
+    /// ffmpeg exited with an error, on the fallback path as well when there was one.
+    EncoderFailed {
+        message: String,
+        stderr: String,
+        log_path: Option<PathBuf>,
+    },
+    /// The frame-by-frame fallback failed outside ffmpeg, such as on a frame it could not
+    /// decode.
+    FallbackFailed {
+        message: String,
+        log_path: Option<PathBuf>,
+    },
+    /// The output could not be written for a reason other than space.
+    OutputFailed(String),
+    DiskFull {
+        message: String,
+        log_path: Option<PathBuf>,
+    },
+    Cancelled,
+    /// The conversion task itself could not be run.
+    Internal(String),
+}
+
+impl ConversionError {
+    /// Encoder failure with nothing captured from ffmpeg yet.
+    pub(crate) fn encoder(message: impl Into<String>) -> Self {
+        Self::EncoderFailed {
+            message: message.into(),
+            stderr: String::new(),
+            log_path: None,
+        }
+    }
+
+    /// Classifies a failed write to the output location.
+    pub(crate) fn output(context: &str, err: &io::Error) -> Self {
+        let message = format!("{}: {}", context, err);
+        if err.kind() == io::ErrorKind::StorageFull {
+            Self::DiskFull {
+                message,
+                log_path: None,
+            }
+        } else {
+            Self::OutputFailed(message)
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InputMissing { .. } => "input_missing",
+            Self::InvalidOptions(_) => "invalid_options",
+            Self::ToolNotFound { .. } => "tool_not_found",
+            Self::ProbeFailed(_) => "probe_failed",
+            Self::EncoderFailed { .. } => "encoder_failed",
+            Self::FallbackFailed { .. } => "fallback_failed",
+            Self::OutputFailed(_) => "output_failed",
+            Self::DiskFull { .. } => "disk_full",
+            Self::Cancelled => "cancelled",
+            Self::Internal(_) => "internal",
+        }
+    }
+
+    /// Debug report written for this failure, if any.
+    pub fn log_path(&self) -> Option<&Path> {
+        match self {
+            Self::ToolNotFound { log_path, .. }
+            | Self::EncoderFailed { log_path, .. }
+            | Self::FallbackFailed { log_path, .. }
+            | Self::DiskFull { log_path, .. } => log_path.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Whether the failure is worth a debug report: the ones that come down to ffmpeg and
+    /// the machine rather than to the input or the options.
+    pub(crate) fn keeps_log(&self) -> bool {
+        matches!(
+            self,
+            Self::ToolNotFound { .. }
+                | Self::EncoderFailed { .. }
+                | Self::FallbackFailed { .. }
+                | Self::DiskFull { .. }
+        )
+    }
+
+    /// Attaches a debug report to the failures that keep one; others are returned unchanged.
+    pub(crate) fn with_log_path(mut self, path: Option<PathBuf>) -> Self {
+        match &mut self {
+            Self::ToolNotFound { log_path, .. }
+            | Self::EncoderFailed { log_path, .. }
+            | Self::FallbackFailed { log_path, .. }
+            | Self::DiskFull { log_path, .. } => *log_path = path,
+            _ => {}
+        }
+        self
+    }
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InputMissing { path } => write!(f, "Input file does not exist: {}", path),
+            Self::InvalidOptions(message)
+            | Self::ProbeFailed(message)
+            | Self::OutputFailed(message)
+            | Self::Internal(message)
+            | Self::ToolNotFound { message, .. }
+            | Self::EncoderFailed { message, .. }
+            | Self::FallbackFailed { message, .. }
+            | Self::DiskFull { message, .. } => f.write_str(message),
+            Self::Cancelled => f.write_str("Conversion cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+impl Serialize for ConversionError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let stderr = match self {
+            Self::EncoderFailed { stderr, .. } => Some(stderr),
+            _ => None,
+        };
+        let mut state = serializer.serialize_struct("ConversionError", 4)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.serialize_field("logPath", &self.log_path())?;
+        state.serialize_field("stderr", &stderr)?;
+        state.end()
+    }
+}
@@ -0,0 +1,112 @@
+//! WebP files built in memory for tests.
+
+use image::codecs::webp::WebPEncoder;
+use image::{ExtendedColorType, Rgba, RgbaImage};
+
+pub const FLAG_NO_BLEND: u8 = 0x02;
+pub const FLAG_DISPOSE: u8 = 0x01;
+
+/// A real 16x16 lossy WebP with an ALPH chunk (VP8X, ALPH, VP8), from CPython's test data,
+/// for the paths lossless fixtures cannot reach.
+pub const LOSSY_ALPHA: &[u8] = include_bytes!("testdata/lossy-alpha.webp");
+
+/// Where LOSSY_ALPHA's ALPH chunk starts, after the RIFF header and the VP8X chunk.
+pub const LOSSY_ALPHA_BITSTREAM: usize = 30;
+
+pub struct TestFrame {
+    pub image: RgbaImage,
+    pub x: u32,
+    pub y: u32,
+    pub flags: u8,
+    pub duration_ms: u32,
+    /// Frame chunks to store instead of a lossless encoding of `image`.
+    pub bitstream: Option<Vec<u8>>,
+}
+
+pub fn frame(width: u32, height: u32, color: Rgba<u8>, x: u32, y: u32, flags: u8) -> TestFrame {
+    TestFrame {
+        image: RgbaImage::from_pixel(width, height, color),
+        x,
+        y,
+        flags,
+        duration_ms: 100,
+        bitstream: None,
+    }
+}
+
+/// LOSSY_ALPHA's ALPH and VP8 chunks as an animation frame; `image` is how it decodes.
+pub fn lossy_frame(x: u32, y: u32, flags: u8) -> TestFrame {
+    TestFrame {
+        image: image::load_from_memory(LOSSY_ALPHA).unwrap().to_rgba8(),
+        x,
+        y,
+        flags,
+        duration_ms: 100,
+        bitstream: Some(LOSSY_ALPHA[LOSSY_ALPHA_BITSTREAM..].to_vec()),
+    }
+}
+
+/// A lossless single-image WebP file.
+pub fn still(image: &RgbaImage) -> Vec<u8> {
+    let mut file = Vec::new();
+    WebPEncoder::new_lossless(&mut file)
+        .encode(
+            image.as_raw(),
+            image.width(),
+            image.height(),
+            ExtendedColorType::Rgba8,
+        )
+        .unwrap();
+    file
+}
+
+/// Builds an animated WebP file laid out as in the container spec: VP8X, ANIM, then one
+/// ANMF chunk per frame. The animation loops forever.
+pub fn animation(width: u32, height: u32, background: Rgba<u8>, frames: &[TestFrame]) -> Vec<u8> {
+    let mut vp8x = vec![0x12, 0, 0, 0];
+    vp8x.extend_from_slice(&u24(width - 1));
+    vp8x.extend_from_slice(&u24(height - 1));
+    let Rgba([r, g, b, a]) = background;
+    let mut body = b"WEBP".to_vec();
+    body.extend(chunk(b"VP8X", &vp8x));
+    body.extend(chunk(b"ANIM", &[b, g, r, a, 0, 0]));
+    for frame in frames {
+        let mut anmf = Vec::new();
+        anmf.extend_from_slice(&u24(frame.x / 2));
+        anmf.extend_from_slice(&u24(frame.y / 2));
+        anmf.extend_from_slice(&u24(frame.image.width() - 1));
+        anmf.extend_from_slice(&u24(frame.image.height() - 1));
+        anmf.extend_from_slice(&u24(frame.duration_ms));
+        anmf.push(frame.flags);
+        match &frame.bitstream {
+            Some(bitstream) => anmf.extend_from_slice(bitstream),
+            // Drop the RIFF/WEBP header, keeping the VP8L chunk.
+            None => anmf.extend_from_slice(&still(&frame.image)[12..]),
+        }
+        body.extend(chunk(b"ANMF", &anmf));
+    }
+    riff(body)
+}
+
+/// Wraps a body that starts with "WEBP" in the RIFF header.
+pub fn riff(body: Vec<u8>) -> Vec<u8> {
+    let mut file = b"RIFF".to_vec();
+    file.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    file.extend(body);
+    file
+}
+
+pub fn chunk(fourcc: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut bytes = fourcc.to_vec();
+    bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(payload);
+    if payload.len() % 2 == 1 {
+        bytes.push(0);
+    }
+    bytes
+}
+
+fn u24(value: u32) -> [u8; 3] {
+    let bytes = value.to_le_bytes();
+    [bytes[0], bytes[1], bytes[2]]
+}
@@ -0,0 +1,197 @@
+use serde::Deserialize;
+
+/// Source region to keep, in canvas pixels.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct CropRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl CropRect {
+    /// Errors when the rectangle is empty or reaches past the canvas.
+    pub fn check(&self, canvas_w: usize, canvas_h: usize) -> Result<(), String> {
+        let fits = self.width > 0
+            && self.height > 0
+            && self.x as usize + self.width as usize <= canvas_w
+            && self.y as usize + self.height as usize <= canvas_h;
+        if fits {
+            Ok(())
+        } else {
+            Err(format!(
+                "Crop rectangle {}x{} at {},{} does not fit the {}x{} canvas",
+                self.width, self.height, self.x, self.y, canvas_w, canvas_h
+            ))
+        }
+    }
+
+    pub fn filter(&self) -> String {
+        format!("crop={}:{}:{}:{}", self.width, self.height, self.x, self.y)
+    }
+}
+
+/// Target width:height ratio for the output canvas, such as 1:1, 9:16 or 16:9.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AspectRatio {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl AspectRatio {
+    pub fn parse(value: Option<&str>) -> Result<Option<Self>, String> {
+        let Some(value) = value.map(str::trim).filter(|value| !value.is_empty()) else {
+            return Ok(None);
+        };
+        let parsed = value.split_once(':').and_then(|(width, height)| {
+            let width = width.trim().parse::<u32>().ok()?;
+            let height = height.trim().parse::<u32>().ok()?;
+            (width > 0 && height > 0).then_some(Self { width, height })
+        });
+        match parsed {
+            Some(aspect) => Ok(Some(aspect)),
+            None => Err(format!(
+                "Invalid aspect ratio '{}'; expected width:height such as 1:1, 9:16 or 16:9",
+                value
+            )),
+        }
+    }
+
+    /// Smallest canvas of this ratio that holds a `w` x `h` frame without scaling it.
+    pub fn canvas_for(&self, w: usize, h: usize) -> (usize, usize) {
+        let (w, h) = (w as u64, h as u64);
+        let (ratio_w, ratio_h) = (self.width as u64, self.height as u64);
+        if w * ratio_h < h * ratio_w {
+            (((h * ratio_w) as f64 / ratio_h as f64).round() as usize, h as usize)
+        } else {
+            (w as usize, ((w * ratio_h) as f64 / ratio_w as f64).round() as usize)
+        }
+    }
+}
+
+/// What fills the space an aspect canvas adds around the frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AspectFill {
+    /// The background color (transparent for outputs with alpha).
+    Color,
+    /// A blurred copy of the frame, scaled up to cover the canvas.
+    Blur,
+}
+
+impl AspectFill {
+    pub fn parse(value: Option<&str>) -> Result<Self, String> {
+        match value.map(|fill| fill.trim().to_lowercase()).as_deref() {
+            None | Some("") | Some("color") => Ok(Self::Color),
+            Some("blur") => Ok(Self::Blur),
+            Some(other) => Err(format!(
+                "Unknown aspect fill '{}'; expected color or blur",
+                other
+            )),
+        }
+    }
+
+    /// Filter steps that center a `w` x `h` frame on a `canvas_w` x `canvas_h` canvas.
+    pub fn filter(self, w: usize, h: usize, canvas_w: usize, canvas_h: usize, color: &str) -> String {
+        match self {
+            Self::Color => format!(
+                "pad={}:{}:(ow-iw)/2:(oh-ih)/2:color={}",
+                canvas_w, canvas_h, color
+            ),
+            Self::Blur => {
+                // boxblur caps the radius at half the smallest (chroma) plane, so stay well
+                // under it.
+                let radius = (canvas_w.min(canvas_h) / 20).max(1);
+                format!(
+                    "split[content][backdrop];[backdrop]scale={cw}:{ch}:force_original_aspect_ratio=increase,crop={cw}:{ch},boxblur={r}:2[blurred];[blurred][content]overlay=({cw}-{w})/2:({ch}-{h})/2:format=auto",
+                    cw = canvas_w,
+                    ch = canvas_h,
+                    r = radius,
+                    w = w,
+                    h = h
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: u32, y: u32, width: u32, height: u32) -> CropRect {
+        CropRect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn crop_must_lie_inside_the_canvas() {
+        assert!(rect(0, 0, 4, 2).check(4, 2).is_ok());
+        assert!(rect(1, 1, 3, 1).check(4, 2).is_ok());
+        assert!(rect(1, 0, 4, 2).check(4, 2).is_err());
+        assert!(rect(0, 1, 4, 2).check(4, 2).is_err());
+        assert!(rect(0, 0, 0, 2).check(4, 2).is_err());
+        assert!(rect(0, 0, 4, 0).check(4, 2).is_err());
+        assert!(rect(u32::MAX, 0, 1, 1).check(4, 2).is_err());
+        assert_eq!(rect(2, 1, 30, 20).filter(), "crop=30:20:2:1");
+    }
+
+    #[test]
+    fn aspect_ratios_parse_as_width_to_height() {
+        let aspect = |width, height| Some(AspectRatio { width, height });
+        assert_eq!(AspectRatio::parse(Some("9:16")), Ok(aspect(9, 16)));
+        assert_eq!(AspectRatio::parse(Some(" 16 : 9 ")), Ok(aspect(16, 9)));
+        assert_eq!(AspectRatio::parse(None), Ok(None));
+        assert_eq!(AspectRatio::parse(Some("  ")), Ok(None));
+        for invalid in ["16x9", "0:1", "1:0", "-1:1", "1.5:1", "square"] {
+            assert!(AspectRatio::parse(Some(invalid)).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn aspect_canvas_grows_the_short_side_only() {
+        let cases = [
+            ((1, 1), (100, 50), (100, 100)),
+            ((1, 1), (50, 100), (100, 100)),
+            ((9, 16), (100, 100), (100, 178)),
+            ((16, 9), (100, 100), (178, 100)),
+            ((16, 9), (160, 90), (160, 90)),
+            ((9, 16), (400, 100), (400, 711)),
+        ];
+        for ((width, height), (w, h), canvas) in cases {
+            let aspect = AspectRatio { width, height };
+            assert_eq!(
+                aspect.canvas_for(w, h),
+                canvas,
+                "{}:{} {}x{}",
+                width,
+                height,
+                w,
+                h
+            );
+        }
+    }
+
+    #[test]
+    fn aspect_fill_pads_with_color_or_a_blurred_copy() {
+        assert_eq!(AspectFill::parse(None), Ok(AspectFill::Color));
+        assert_eq!(AspectFill::parse(Some("Blur")), Ok(AspectFill::Blur));
+        assert!(AspectFill::parse(Some("mirror")).is_err());
+
+        assert_eq!(
+            AspectFill::Color.filter(100, 50, 100, 100, "white"),
+            "pad=100:100:(ow-iw)/2:(oh-ih)/2:color=white"
+        );
+        assert_eq!(
+            AspectFill::Blur.filter(100, 50, 100, 100, "white"),
+            "split[content][backdrop];[backdrop]scale=100:100:force_original_aspect_ratio=increase,crop=100:100,boxblur=5:2[blurred];[blurred][content]overlay=(100-100)/2:(100-50)/2:format=auto"
+        );
+        // Tiny canvases still get a radius boxblur accepts.
+        assert!(AspectFill::Blur
+            .filter(10, 4, 10, 10, "white")
+            .contains("boxblur=1:2"));
+    }
+}
@@ -0,0 +1,128 @@
+use std::fs;
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Receives job progress from the conversion engine.
+pub trait ProgressReporter: Sync {
+    /// `progress` is a percentage of the whole job; `stage` names what it is doing.
+    fn report(&self, job_id: &str, progress: u8, stage: &str, eta_seconds: Option<u64>);
+}
+
+/// Finds the external tools a conversion runs.
+pub trait ToolLocator: Sync {
+    fn ffmpeg_path(&self) -> Result<PathBuf, String>;
+}
+
+/// Keeps failure reports so users can attach them to bug reports.
+pub trait LogStore: Sync {
+    /// Stores `contents` and returns where it went, or `None` when it could not be written.
+    fn write(&self, contents: &str) -> Option<PathBuf>;
+}
+
+/// Looks for ffmpeg in an explicit location, then the app bundle, then the dev tree, then
+/// the system PATH.
+pub struct BundledTools {
+    /// Binary chosen by the user, tried before anything else.
+    pub ffmpeg: Option<PathBuf>,
+    /// The bundle's resource directory.
+    pub resource_dir: Option<PathBuf>,
+}
+
+impl ToolLocator for BundledTools {
+    fn ffmpeg_path(&self) -> Result<PathBuf, String> {
+        if let Some(path) = &self.ffmpeg {
+            ensure_executable(path)?;
+            return Ok(path.clone());
+        }
+
+        // Try to get bundled FFmpeg first
+        if let Some(resource_path) = &self.resource_dir {
+            let bundled_ffmpeg = resource_path
+                .join("resources")
+                .join("ffmpeg")
+                .join("ffmpeg");
+            if bundled_ffmpeg.exists() {
+                ensure_executable(&bundled_ffmpeg)?;
+                return Ok(bundled_ffmpeg);
+            }
+
+            let legacy_ffmpeg = resource_path.join("ffmpeg").join("ffmpeg");
+            if legacy_ffmpeg.exists() {
+                ensure_executable(&legacy_ffmpeg)?;
+                return Ok(legacy_ffmpeg);
+            }
+        }
+
+        // Fallback to dev environment
+        let dev_ffmpeg = PathBuf::from("src-tauri/resources/ffmpeg/ffmpeg");
+        if dev_ffmpeg.exists() {
+            ensure_executable(&dev_ffmpeg)?;
+            return Ok(dev_ffmpeg);
+        }
+
+        let dev_legacy = PathBuf::from("src-tauri/resources/ffmpeg/ffmpeg");
+        if dev_legacy.exists() {
+            ensure_executable(&dev_legacy)?;
+            return Ok(dev_legacy);
+        }
+
+        // Last resort: system FFmpeg
+        let system_ffmpeg = PathBuf::from("ffmpeg");
+        ensure_executable(&system_ffmpeg)?;
+        Ok(system_ffmpeg)
+    }
+}
+
+/// Writes each report to its own timestamped file in a directory, or in the system temp
+/// directory when there is none.
+pub struct LogDir(pub Option<PathBuf>);
+
+impl LogStore for LogDir {
+    fn write(&self, contents: &str) -> Option<PathBuf> {
+        let dir = self.0.clone().unwrap_or_else(std::env::temp_dir);
+        let _ = fs::create_dir_all(&dir);
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()?
+            .as_millis();
+        let path = dir.join(format!("webpconv-error-{}.log", stamp));
+        if fs::write(&path, contents).is_ok() {
+            Some(path)
+        } else {
+            None
+        }
+    }
+}
+
+fn ensure_executable(path: &Path) -> Result<(), String> {
+    if is_bare_command(path) {
+        return Ok(());
+    }
+    if !path.exists() {
+        return Err(format!("Tool not found at {}", path.display()));
+    }
+    #[cfg(unix)]
+    {
+        let metadata = fs::metadata(path)
+            .map_err(|e| format!("Failed to read metadata for {}: {}", path.display(), e))?;
+        let mut perms = metadata.permissions();
+        let mode = perms.mode();
+        if mode & 0o111 == 0 {
+            perms.set_mode(mode | 0o111);
+            fs::set_permissions(path, perms).map_err(|e| {
+                format!("Failed to set executable bit for {}: {}", path.display(), e)
+            })?;
+        }
+    }
+    Ok(())
+}
+
+fn is_bare_command(path: &Path) -> bool {
+    path.components().count() == 1
+        && path
+            .to_string_lossy()
+            .chars()
+            .all(|c| c != '/' && c != '\\')
+}
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+
+use crate::error::ConversionError;
+
+/// Running conversions keyed by job id, so they can be cancelled from another command.
+#[derive(Default)]
+pub struct JobRegistry {
+    jobs: Mutex<HashMap<String, Arc<JobHandle>>>,
+}
+
+impl JobRegistry {
+    pub fn register(&self, job_id: &str) -> Arc<JobHandle> {
+        let handle = Arc::new(JobHandle {
+            id: job_id.to_string(),
+            cancelled: AtomicBool::new(false),
+            processes: Mutex::new(Vec::new()),
+        });
+        if let Ok(mut jobs) = self.jobs.lock() {
+            jobs.insert(job_id.to_string(), handle.clone());
+        }
+        handle
+    }
+
+    pub fn remove(&self, job_id: &str) {
+        if let Ok(mut jobs) = self.jobs.lock() {
+            jobs.remove(job_id);
+        }
+    }
+
+    /// Returns false when no job with that id is running.
+    pub fn cancel(&self, job_id: &str) -> bool {
+        let handle = match self.jobs.lock() {
+            Ok(jobs) => jobs.get(job_id).cloned(),
+            Err(_) => None,
+        };
+        match handle {
+            Some(handle) => {
+                handle.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+pub struct JobHandle {
+    pub id: String,
+    cancelled: AtomicBool,
+    processes: Mutex<Vec<u32>>,
+}
+
+impl JobHandle {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Returns an error once the job has been cancelled, for use between pipeline steps.
+    pub fn check_cancelled(&self) -> Result<(), ConversionError> {
+        if self.is_cancelled() {
+            Err(ConversionError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        if let Ok(processes) = self.processes.lock() {
+            for pid in processes.iter() {
+                kill_process_tree(*pid);
+            }
+        }
+    }
+
+    /// Tracks a spawned process so cancelling the job kills it. A process attached after
+    /// cancellation is killed straight away.
+    pub fn attach(&self, pid: u32) {
+        if let Ok(mut processes) = self.processes.lock() {
+            processes.push(pid);
+        }
+        if self.is_cancelled() {
+            kill_process_tree(pid);
+        }
+    }
+
+    /// Stops tracking a process. Call it once the process has exited but before it is
+    /// reaped, while its pid cannot belong to anything else.
+    pub fn detach(&self, pid: u32) {
+        if let Ok(mut processes) = self.processes.lock() {
+            processes.retain(|tracked| *tracked != pid);
+        }
+    }
+}
+
+/// Starts the child in its own process group so the whole tree can be killed at once.
+pub fn isolate_process_group(cmd: &mut Command) -> &mut Command {
+    #[cfg(unix)]
+    cmd.process_group(0);
+    cmd
+}
+
+fn kill_process_tree(pid: u32) {
+    #[cfg(unix)]
+    unsafe {
+        // The child leads its own group (see isolate_process_group), so its pgid is its pid.
+        libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+    }
+    #[cfg(windows)]
+    {
+        let _ = Command::new("taskkill")
+            .args(["/PID", &pid.to_string(), "/T", "/F"])
+            .output();
+    }
+}
@@ -0,0 +1,366 @@
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::engine::{AdvancedOptions, ConversionSettings, ConvertOptions};
+use crate::error::ConversionError;
+
+const PRESET_FILE_VERSION: u32 = 1;
+
+/// A named bundle of conversion options. Unset fields fall back to whatever the conversion
+/// request or the defaults say.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Preset {
+    pub name: String,
+    #[serde(default)]
+    pub quality: Option<String>,
+    #[serde(default)]
+    pub codec: Option<String>,
+    #[serde(default)]
+    pub crf: Option<u8>,
+    #[serde(default)]
+    pub fps: Option<u32>,
+    #[serde(default)]
+    pub background: Option<String>,
+    #[serde(default)]
+    pub output_format: Option<String>,
+    #[serde(default)]
+    pub output_name_template: Option<String>,
+    #[serde(default)]
+    pub static_duration: Option<f64>,
+}
+
+impl Preset {
+    /// Fills in the options the request leaves unset. Anything set explicitly on the request
+    /// wins over the preset.
+    pub fn apply_to(&self, options: &ConvertOptions) -> ConvertOptions {
+        let mut merged = options.clone();
+        merged.quality = merged.quality.or_else(|| self.quality.clone());
+        merged.codec = merged.codec.or_else(|| self.codec.clone());
+        merged.fps = merged.fps.or(self.fps);
+        merged.background = merged.background.or_else(|| self.background.clone());
+        merged.output_format = merged.output_format.or_else(|| self.output_format.clone());
+        merged.output_name_template = merged
+            .output_name_template
+            .or_else(|| self.output_name_template.clone());
+        merged.static_duration = merged.static_duration.or(self.static_duration);
+        // A target size or crf on the request replaces the preset's crf. Quality does not: the
+        // app always sends one, and the preset's crf is the more specific choice, leaving
+        // quality to pick the encoder speed.
+        let request_sets_rate = options.target_size_bytes.is_some_and(|bytes| bytes > 0)
+            || options
+                .advanced
+                .as_ref()
+                .is_some_and(|advanced| advanced.crf.is_some());
+        if let Some(crf) = self.crf.filter(|_| !request_sets_rate) {
+            merged
+                .advanced
+                .get_or_insert_with(AdvancedOptions::default)
+                .crf = Some(crf);
+        }
+        merged
+    }
+
+    /// Rejects presets that could never be used for a conversion.
+    fn validate(&self) -> Result<(), ConversionError> {
+        if self.name.trim().is_empty() {
+            return Err(ConversionError::InvalidOptions(
+                "Preset name cannot be empty".to_string(),
+            ));
+        }
+        ConversionSettings::from_options(&self.apply_to(&ConvertOptions::default()))
+            .map(|_| ())
+            .map_err(|e| {
+                ConversionError::InvalidOptions(format!("Invalid preset '{}': {}", self.name, e))
+            })
+    }
+}
+
+/// On-disk layout of the library and of exported preset files.
+#[derive(Serialize, Deserialize)]
+struct PresetFile {
+    version: u32,
+    presets: Vec<Preset>,
+}
+
+/// Reads the library at `path`. A missing file is an empty library.
+pub fn load(path: &Path) -> Result<Vec<Preset>, ConversionError> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    read_presets(path)
+}
+
+/// Adds `preset`, replacing any preset with the same name, and returns the updated library.
+pub fn save(path: &Path, preset: Preset) -> Result<Vec<Preset>, ConversionError> {
+    let mut preset = preset;
+    preset.name = preset.name.trim().to_string();
+    preset.validate()?;
+    let mut presets = load(path)?;
+    upsert(&mut presets, preset);
+    write_presets(path, &presets)?;
+    Ok(presets)
+}
+
+/// Returns false when no preset has that name.
+pub fn delete(path: &Path, name: &str) -> Result<bool, ConversionError> {
+    let mut presets = load(path)?;
+    let before = presets.len();
+    presets.retain(|preset| preset.name != name);
+    if presets.len() == before {
+        return Ok(false);
+    }
+    write_presets(path, &presets)?;
+    Ok(true)
+}
+
+/// Merges the presets in `source` into the library. Imported presets replace existing ones
+/// with the same name. Nothing is written unless every imported preset is valid.
+pub fn import(path: &Path, source: &Path) -> Result<Vec<Preset>, ConversionError> {
+    let mut imported = read_presets(source)?;
+    for preset in &mut imported {
+        preset.name = preset.name.trim().to_string();
+        preset.validate()?;
+    }
+    let mut presets = load(path)?;
+    for preset in imported {
+        upsert(&mut presets, preset);
+    }
+    write_presets(path, &presets)?;
+    Ok(presets)
+}
+
+/// Writes the named presets, or the whole library when `names` is `None`, to `destination`.
+/// Returns how many presets were exported.
+pub fn export(
+    path: &Path,
+    destination: &Path,
+    names: Option<&[String]>,
+) -> Result<usize, ConversionError> {
+    let presets: Vec<Preset> = load(path)?
+        .into_iter()
+        .filter(|preset| names.is_none_or(|names| names.contains(&preset.name)))
+        .collect();
+    if let Some(names) = names {
+        if let Some(missing) = names
+            .iter()
+            .find(|name| !presets.iter().any(|preset| &preset.name == *name))
+        {
+            return Err(missing_preset(missing));
+        }
+    }
+    write_presets(destination, &presets)?;
+    Ok(presets.len())
+}
+
+pub fn find(path: &Path, name: &str) -> Result<Preset, ConversionError> {
+    load(path)?
+        .into_iter()
+        .find(|preset| preset.name == name)
+        .ok_or_else(|| missing_preset(name))
+}
+
+fn missing_preset(name: &str) -> ConversionError {
+    ConversionError::InvalidOptions(format!("Preset '{}' does not exist", name))
+}
+
+fn upsert(presets: &mut Vec<Preset>, preset: Preset) {
+    match presets
+        .iter_mut()
+        .find(|existing| existing.name == preset.name)
+    {
+        Some(existing) => *existing = preset,
+        None => presets.push(preset),
+    }
+}
+
+/// Accepts the versioned preset file as well as a bare array of presets, which is easier to
+/// write by hand.
+/// Unreadable files are output failures; files that are not a preset library are invalid
+/// options.
+fn read_presets(path: &Path) -> Result<Vec<Preset>, ConversionError> {
+    let contents = fs::read_to_string(path).map_err(|e| {
+        ConversionError::OutputFailed(format!(
+            "Failed to read presets from {}: {}",
+            path.display(),
+            e
+        ))
+    })?;
+    let file = serde_json::from_str::<PresetFile>(&contents).or_else(|file_err| {
+        serde_json::from_str::<Vec<Preset>>(&contents)
+            .map(|presets| PresetFile {
+                version: PRESET_FILE_VERSION,
+                presets,
+            })
+            .map_err(|_| file_err)
+    });
+    let file = file.map_err(|e| {
+        ConversionError::InvalidOptions(format!("Invalid preset file {}: {}", path.display(), e))
+    })?;
+    if file.version > PRESET_FILE_VERSION {
+        return Err(ConversionError::InvalidOptions(format!(
+            "Preset file {} uses version {}, newer than this app supports ({})",
+            path.display(),
+            file.version,
+            PRESET_FILE_VERSION
+        )));
+    }
+    Ok(file.presets)
+}
+
+/// Writes through a temporary file so a failed write never truncates the library.
+fn write_presets(path: &Path, presets: &[Preset]) -> Result<(), ConversionError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| ConversionError::output("Failed to create preset directory", &e))?;
+    }
+    let file = PresetFile {
+        version: PRESET_FILE_VERSION,
+        presets: presets.to_vec(),
+    };
+    let json = serde_json::to_string_pretty(&file).map_err(|e| {
+        ConversionError::OutputFailed(format!("Failed to serialize presets: {}", e))
+    })?;
+    let context = format!("Failed to write presets to {}", path.display());
+    let temp = path.with_extension("json.tmp");
+    fs::write(&temp, json).map_err(|e| ConversionError::output(&context, &e))?;
+    fs::rename(&temp, path).map_err(|e| ConversionError::output(&context, &e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::TempDir;
+    use serde_json::json;
+
+    fn preset(value: serde_json::Value) -> Preset {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn options(value: serde_json::Value) -> ConvertOptions {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn crf(options: &ConvertOptions) -> Option<u8> {
+        options.advanced.as_ref().and_then(|advanced| advanced.crf)
+    }
+
+    fn names(presets: &[Preset]) -> Vec<&str> {
+        presets.iter().map(|preset| preset.name.as_str()).collect()
+    }
+
+    #[test]
+    fn preset_values_fill_only_unset_fields() {
+        let small = preset(
+            json!({ "name": "small", "quality": "small", "fps": 15, "codec": "hevc", "crf": 30 }),
+        );
+        let merged = small.apply_to(&options(json!({ "fps": 30 })));
+        assert_eq!(merged.fps, Some(30));
+        assert_eq!(merged.quality.as_deref(), Some("small"));
+        assert_eq!(merged.codec.as_deref(), Some("hevc"));
+        assert_eq!(crf(&merged), Some(30));
+    }
+
+    #[test]
+    fn only_a_target_size_or_crf_on_the_request_replaces_the_preset_crf() {
+        let small = preset(json!({ "name": "small", "crf": 30 }));
+        let merged = small.apply_to(&options(json!({ "quality": "high" })));
+        assert_eq!(merged.quality.as_deref(), Some("high"));
+        assert_eq!(crf(&merged), Some(30));
+        let settings = ConversionSettings::from_options(&merged).unwrap();
+        assert_eq!(settings.tuning.crf, 30);
+
+        let merged = small.apply_to(&options(json!({ "targetSizeBytes": 1_000_000 })));
+        assert_eq!(crf(&merged), None);
+        assert!(ConversionSettings::from_options(&merged).is_ok());
+
+        let merged = small.apply_to(&options(
+            json!({ "advanced": { "crf": 20, "tune": "film" } }),
+        ));
+        assert_eq!(crf(&merged), Some(20));
+
+        let merged = small.apply_to(&options(json!({ "advanced": { "tune": "film" } })));
+        assert_eq!(crf(&merged), Some(30));
+        let tune = merged.advanced.and_then(|advanced| advanced.tune);
+        assert_eq!(tune.as_deref(), Some("film"));
+    }
+
+    #[test]
+    fn library_round_trips_through_save_delete_import_and_export() {
+        let dir = TempDir::create("webpconv-presets").unwrap();
+        let library = dir.path().join("presets.json");
+        assert!(load(&library).unwrap().is_empty());
+
+        save(
+            &library,
+            preset(json!({ "name": " web ", "quality": "small" })),
+        )
+        .unwrap();
+        save(
+            &library,
+            preset(json!({ "name": "gif", "outputFormat": "gif" })),
+        )
+        .unwrap();
+        let saved = save(&library, preset(json!({ "name": "web", "fps": 24 }))).unwrap();
+        assert_eq!(names(&saved), ["web", "gif"]);
+        assert_eq!(saved[0].fps, Some(24));
+        assert_eq!(saved[0].quality, None);
+        assert!(!library.with_extension("json.tmp").exists());
+        assert_eq!(names(&load(&library).unwrap()), ["web", "gif"]);
+
+        let exported = dir.path().join("exported.json");
+        let only_gif = ["gif".to_string()];
+        assert_eq!(export(&library, &exported, Some(&only_gif)).unwrap(), 1);
+        let missing = ["nope".to_string()];
+        assert!(export(&library, &exported, Some(&missing)).is_err());
+
+        assert!(delete(&library, "gif").unwrap());
+        assert!(!delete(&library, "gif").unwrap());
+        assert_eq!(names(&load(&library).unwrap()), ["web"]);
+
+        let imported = import(&library, &exported).unwrap();
+        assert_eq!(names(&imported), ["web", "gif"]);
+        assert_eq!(
+            find(&library, "gif").unwrap().output_format.as_deref(),
+            Some("gif")
+        );
+    }
+
+    #[test]
+    fn import_accepts_a_bare_array_and_rejects_invalid_presets() {
+        let dir = TempDir::create("webpconv-presets").unwrap();
+        let library = dir.path().join("presets.json");
+        let source = dir.path().join("hand-written.json");
+
+        fs::write(&source, r#"[{ "name": "tiny", "crf": 40 }]"#).unwrap();
+        assert_eq!(names(&import(&library, &source).unwrap()), ["tiny"]);
+
+        fs::write(
+            &source,
+            r#"[{ "name": "ok" }, { "name": "bad", "codec": "mpeg2" }]"#,
+        )
+        .unwrap();
+        assert!(import(&library, &source).is_err());
+        assert_eq!(names(&load(&library).unwrap()), ["tiny"]);
+
+        fs::write(&source, r#"{ "version": 99, "presets": [] }"#).unwrap();
+        assert_eq!(
+            import(&library, &source).unwrap_err().code(),
+            "invalid_options"
+        );
+        fs::write(&source, "not json").unwrap();
+        assert_eq!(
+            import(&library, &source).unwrap_err().code(),
+            "invalid_options"
+        );
+        let unreadable = dir.path().join("missing.json");
+        assert_eq!(
+            import(&library, &unreadable).unwrap_err().code(),
+            "output_failed"
+        );
+        let blank = preset(json!({ "name": "  " }));
+        assert_eq!(save(&library, blank).unwrap_err().code(), "invalid_options");
+    }
+}
@@ -0,0 +1,174 @@
+use std::io::BufRead;
+use std::time::Instant;
+
+/// What an encode is measured against when turning `-progress` output into a percentage.
+#[derive(Debug, Clone, Copy)]
+pub enum ProgressTotal {
+    DurationMs(u64),
+    Frames(u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProgressUpdate {
+    pub percent: u8,
+    pub eta_seconds: Option<u64>,
+}
+
+/// Reads ffmpeg `-progress` key/value output until the stream closes, calling `on_update`
+/// whenever the percentage moves. Percentages stop at 99; the caller reports completion.
+pub fn watch_ffmpeg_progress(
+    reader: impl BufRead,
+    total: ProgressTotal,
+    mut on_update: impl FnMut(ProgressUpdate),
+) {
+    let started = Instant::now();
+    let mut parser = ProgressParser::new(total);
+    for line in reader.lines() {
+        let Ok(line) = line else { break };
+        if let Some(update) = parser.feed(&line, started.elapsed().as_secs_f64()) {
+            on_update(update);
+        }
+    }
+}
+
+/// Turns `-progress` lines into updates, one block at a time. Each block of key=value lines
+/// ends with a `progress=` line, which is when an update can be due.
+pub struct ProgressParser {
+    total: ProgressTotal,
+    frame: Option<u64>,
+    out_time_us: Option<u64>,
+    last_percent: Option<u8>,
+}
+
+impl ProgressParser {
+    pub fn new(total: ProgressTotal) -> Self {
+        Self {
+            total,
+            frame: None,
+            out_time_us: None,
+            last_percent: None,
+        }
+    }
+
+    /// Takes one line, with `elapsed_secs` of encoding so far for the ETA. Returns an update
+    /// when a block ends on a new percentage.
+    pub fn feed(&mut self, line: &str, elapsed_secs: f64) -> Option<ProgressUpdate> {
+        let (key, value) = line.trim().split_once('=')?;
+        match key {
+            "frame" => self.frame = value.trim().parse::<u64>().ok(),
+            // Despite its name, ffmpeg reports out_time_ms in microseconds as well.
+            "out_time_us" | "out_time_ms" => {
+                if let Ok(us) = value.trim().parse::<u64>() {
+                    self.out_time_us = Some(us);
+                }
+            }
+            "progress" => {
+                let fraction = progress_fraction(self.total, self.frame, self.out_time_us)?;
+                let percent = (fraction * 100.0).floor().min(99.0) as u8;
+                if self.last_percent == Some(percent) {
+                    return None;
+                }
+                self.last_percent = Some(percent);
+                return Some(ProgressUpdate {
+                    percent,
+                    eta_seconds: estimate_remaining(elapsed_secs, fraction),
+                });
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+fn progress_fraction(total: ProgressTotal, frame: Option<u64>, out_time_us: Option<u64>) -> Option<f64> {
+    let fraction = match total {
+        ProgressTotal::Frames(total) if total > 0 => frame? as f64 / total as f64,
+        ProgressTotal::DurationMs(total) if total > 0 => out_time_us? as f64 / 1000.0 / total as f64,
+        _ => return None,
+    };
+    Some(fraction.clamp(0.0, 1.0))
+}
+
+fn estimate_remaining(elapsed_secs: f64, fraction: f64) -> Option<u64> {
+    if fraction <= 0.0 {
+        return None;
+    }
+    Some((elapsed_secs / fraction - elapsed_secs).max(0.0).round() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds `lines` all at `elapsed_secs` and collects the updates.
+    fn updates(total: ProgressTotal, lines: &[&str], elapsed_secs: f64) -> Vec<ProgressUpdate> {
+        let mut parser = ProgressParser::new(total);
+        lines
+            .iter()
+            .filter_map(|line| parser.feed(line, elapsed_secs))
+            .collect()
+    }
+
+    fn update(percent: u8, eta_seconds: Option<u64>) -> ProgressUpdate {
+        ProgressUpdate {
+            percent,
+            eta_seconds,
+        }
+    }
+
+    #[test]
+    fn each_block_reports_on_its_progress_line() {
+        let lines = [
+            "frame=25",
+            "fps=0.00",
+            "out_time_us=1000000",
+            "out_time=00:00:01.000000",
+            "progress=continue",
+            "frame=50",
+            "out_time_ms=2500000",
+            "progress=continue",
+        ];
+        assert_eq!(
+            updates(ProgressTotal::DurationMs(10_000), &lines, 5.0),
+            [update(10, Some(45)), update(25, Some(15))]
+        );
+        assert_eq!(
+            updates(ProgressTotal::Frames(200), &lines, 5.0),
+            [update(12, Some(35)), update(25, Some(15))]
+        );
+    }
+
+    #[test]
+    fn completion_is_left_to_the_caller() {
+        let lines = [
+            "out_time_us=9999000",
+            "progress=continue",
+            "out_time_us=12000000",
+            "progress=end",
+        ];
+        assert_eq!(
+            updates(ProgressTotal::DurationMs(10_000), &lines, 10.0),
+            [update(99, Some(0))]
+        );
+    }
+
+    #[test]
+    fn unchanged_or_unknown_progress_is_not_reported() {
+        let lines = [
+            "out_time_us=N/A",
+            "progress=continue",
+            "out_time_us=1000",
+            "progress=continue",
+            "out_time_us=2000",
+            "progress=continue",
+            "garbage",
+        ];
+        // Nothing done yet gives no ETA, and 0.2% rounds down to the same 0%.
+        assert_eq!(
+            updates(ProgressTotal::DurationMs(1000), &lines, 1.0),
+            [update(0, Some(999))]
+        );
+        assert!(updates(ProgressTotal::DurationMs(0), &lines, 1.0).is_empty());
+        assert!(updates(ProgressTotal::Frames(10), &lines, 1.0).is_empty());
+    }
+}
@@ -0,0 +1,277 @@
+use std::collections::HashSet;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+
+use crate::engine::{ConversionResult, ConvertOptions};
+use crate::error::ConversionError;
+use crate::jobs::{JobHandle, JobRegistry};
+
+#[derive(Clone)]
+pub struct QueuedJob {
+    pub job_id: String,
+    pub input_path: String,
+    pub options: ConvertOptions,
+    pub priority: i32,
+    /// Receives the outcome when a caller is waiting on this one job. Dropped unsent if the
+    /// job is removed before it starts.
+    pub done: Option<Sender<Result<ConversionResult, ConversionError>>>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueSnapshot {
+    pub pending: Vec<String>,
+    pub running: Vec<String>,
+    pub max_parallel: usize,
+}
+
+/// Pending conversions plus the set currently running, capped at `max_parallel`.
+///
+/// Jobs start highest priority first; equal priorities start in queue order.
+pub struct ConversionQueue {
+    state: Mutex<QueueState>,
+}
+
+struct QueueState {
+    pending: Vec<QueuedJob>,
+    running: HashSet<String>,
+    max_parallel: usize,
+}
+
+impl Default for ConversionQueue {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(QueueState {
+                pending: Vec::new(),
+                running: HashSet::new(),
+                max_parallel: default_max_parallel(),
+            }),
+        }
+    }
+}
+
+impl ConversionQueue {
+    /// Adds jobs to the back of the queue, skipping ids that are already queued or running.
+    /// Returns the jobs that were accepted.
+    pub fn enqueue(&self, jobs: Vec<QueuedJob>) -> Vec<QueuedJob> {
+        let mut state = self.lock();
+        let mut accepted = Vec::new();
+        for job in jobs {
+            let duplicate = state.running.contains(&job.job_id)
+                || state.pending.iter().any(|queued| queued.job_id == job.job_id);
+            if !duplicate {
+                state.pending.push(job.clone());
+                accepted.push(job);
+            }
+        }
+        accepted
+    }
+
+    /// Moves the listed pending jobs to the front in the given order. Unknown ids are ignored
+    /// and unlisted jobs keep their relative order behind them.
+    pub fn reorder(&self, job_ids: &[String]) {
+        let mut state = self.lock();
+        let mut reordered = Vec::with_capacity(state.pending.len());
+        for job_id in job_ids {
+            if let Some(index) = state.pending.iter().position(|job| &job.job_id == job_id) {
+                reordered.push(state.pending.remove(index));
+            }
+        }
+        reordered.append(&mut state.pending);
+        state.pending = reordered;
+    }
+
+    pub fn set_priority(&self, job_id: &str, priority: i32) -> bool {
+        let mut state = self.lock();
+        match state.pending.iter_mut().find(|job| job.job_id == job_id) {
+            Some(job) => {
+                job.priority = priority;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sets the parallel job limit; 0 restores the CPU core count.
+    pub fn set_max_parallel(&self, max_parallel: usize) -> usize {
+        let mut state = self.lock();
+        state.max_parallel = if max_parallel == 0 {
+            default_max_parallel()
+        } else {
+            max_parallel
+        };
+        state.max_parallel
+    }
+
+    /// Drops a job that has not started yet. Returns false if it is running or unknown.
+    pub fn remove_pending(&self, job_id: &str) -> bool {
+        let mut state = self.lock();
+        let before = state.pending.len();
+        state.pending.retain(|job| job.job_id != job_id);
+        state.pending.len() != before
+    }
+
+    /// Marks as many pending jobs as running as the limit allows and returns them, each
+    /// registered in `jobs` before the queue is unlocked. A cancel therefore always finds the
+    /// job either still pending or in the registry.
+    pub fn take_ready(&self, jobs: &JobRegistry) -> Vec<(QueuedJob, Arc<JobHandle>)> {
+        let mut state = self.lock();
+        let mut ready = Vec::new();
+        while state.running.len() < state.max_parallel && !state.pending.is_empty() {
+            let mut next = 0;
+            for (index, job) in state.pending.iter().enumerate() {
+                if job.priority > state.pending[next].priority {
+                    next = index;
+                }
+            }
+            let job = state.pending.remove(next);
+            state.running.insert(job.job_id.clone());
+            let handle = jobs.register(&job.job_id);
+            ready.push((job, handle));
+        }
+        ready
+    }
+
+    pub fn finish(&self, job_id: &str) {
+        self.lock().running.remove(job_id);
+    }
+
+    pub fn snapshot(&self) -> QueueSnapshot {
+        let state = self.lock();
+        QueueSnapshot {
+            pending: state.pending.iter().map(|job| job.job_id.clone()).collect(),
+            running: state.running.iter().cloned().collect(),
+            max_parallel: state.max_parallel,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn default_max_parallel() -> usize {
+    std::thread::available_parallelism()
+        .map(|count| count.get())
+        .unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(job_id: &str, priority: i32) -> QueuedJob {
+        QueuedJob {
+            job_id: job_id.to_string(),
+            input_path: format!("{}.webp", job_id),
+            options: ConvertOptions::default(),
+            priority,
+            done: None,
+        }
+    }
+
+    fn queue(max_parallel: usize, jobs: Vec<QueuedJob>) -> ConversionQueue {
+        let queue = ConversionQueue::default();
+        queue.set_max_parallel(max_parallel);
+        queue.enqueue(jobs);
+        queue
+    }
+
+    fn take(queue: &ConversionQueue, jobs: &JobRegistry) -> Vec<String> {
+        queue
+            .take_ready(jobs)
+            .into_iter()
+            .map(|(job, _)| job.job_id)
+            .collect()
+    }
+
+    #[test]
+    fn jobs_start_by_priority_then_queue_order() {
+        let jobs = JobRegistry::default();
+        let queue = queue(
+            10,
+            vec![
+                job("a", 0),
+                job("b", 5),
+                job("c", 0),
+                job("d", 5),
+                job("e", -1),
+            ],
+        );
+        assert_eq!(take(&queue, &jobs), ["b", "d", "a", "c", "e"]);
+    }
+
+    #[test]
+    fn duplicate_ids_are_not_queued_twice() {
+        let jobs = JobRegistry::default();
+        let queue = queue(1, vec![job("a", 0), job("a", 0)]);
+        assert_eq!(queue.snapshot().pending, ["a"]);
+        take(&queue, &jobs);
+        assert!(queue.enqueue(vec![job("a", 0)]).is_empty());
+        assert_eq!(queue.enqueue(vec![job("b", 0)]).len(), 1);
+    }
+
+    #[test]
+    fn take_ready_stops_at_the_limit() {
+        let jobs = JobRegistry::default();
+        let queue = queue(2, vec![job("a", 0), job("b", 0), job("c", 0)]);
+        assert_eq!(take(&queue, &jobs), ["a", "b"]);
+        assert!(take(&queue, &jobs).is_empty());
+        queue.finish("a");
+        assert_eq!(take(&queue, &jobs), ["c"]);
+        let snapshot = queue.snapshot();
+        assert!(snapshot.pending.is_empty());
+        assert_eq!(snapshot.running.len(), 2);
+    }
+
+    #[test]
+    fn started_jobs_can_be_cancelled_through_the_registry() {
+        let jobs = JobRegistry::default();
+        let queue = queue(1, vec![job("a", 0), job("b", 0)]);
+        let ready = queue.take_ready(&jobs);
+        // Running jobs are no longer pending, but the registry already knows them.
+        assert!(!queue.remove_pending("a"));
+        assert!(jobs.cancel("a"));
+        assert!(ready[0].1.is_cancelled());
+        assert!(!jobs.cancel("b"));
+    }
+
+    #[test]
+    fn reorder_moves_listed_jobs_to_the_front() {
+        let queue = queue(1, vec![job("a", 0), job("b", 0), job("c", 0), job("d", 0)]);
+        queue.reorder(&["c".to_string(), "x".to_string(), "a".to_string()]);
+        assert_eq!(queue.snapshot().pending, ["c", "a", "b", "d"]);
+    }
+
+    #[test]
+    fn set_priority_only_reaches_pending_jobs() {
+        let jobs = JobRegistry::default();
+        let queue = queue(1, vec![job("a", 0), job("b", 0), job("c", 0)]);
+        take(&queue, &jobs);
+        assert!(queue.set_priority("c", 1));
+        assert!(!queue.set_priority("a", 1));
+        assert!(!queue.set_priority("x", 1));
+        queue.finish("a");
+        assert_eq!(take(&queue, &jobs), ["c"]);
+    }
+
+    #[test]
+    fn zero_parallel_restores_the_core_count() {
+        let queue = ConversionQueue::default();
+        assert_eq!(queue.set_max_parallel(3), 3);
+        assert_eq!(queue.snapshot().max_parallel, 3);
+        let cores = queue.set_max_parallel(0);
+        assert!(cores >= 1);
+        assert_eq!(cores, default_max_parallel());
+    }
+
+    #[test]
+    fn remove_pending_drops_only_waiting_jobs() {
+        let queue = queue(1, vec![job("a", 0), job("b", 0)]);
+        assert!(queue.remove_pending("a"));
+        assert!(!queue.remove_pending("a"));
+        assert_eq!(queue.snapshot().pending, ["b"]);
+    }
+}
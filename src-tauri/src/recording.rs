@@ -0,0 +1,136 @@
+//! A process runner for tests that records commands instead of running them.
+
+use std::fs;
+use std::io::{self, Cursor, Read, Write};
+use std::sync::{Arc, Mutex};
+
+use crate::runner::{ProcessRunner, ToolCommand, ToolProcess};
+
+/// A command the [`RecordingRunner`] was asked to run, with how much was streamed into it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedCall {
+    pub command: ToolCommand,
+    pub stdin_bytes: u64,
+}
+
+/// Fake runner that records commands instead of running them. Every process swallows its
+/// stdin, prints nothing on stdout, and either succeeds, creating the file named by its last
+/// argument the way ffmpeg writes its output, or fails with the configured stderr.
+#[derive(Default)]
+pub struct RecordingRunner {
+    calls: Arc<Mutex<Vec<RecordedCall>>>,
+    failure: Option<String>,
+}
+
+impl RecordingRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A runner whose processes all exit with an error after printing `stderr`.
+    pub fn failing(stderr: &str) -> Self {
+        Self {
+            calls: Arc::default(),
+            failure: Some(stderr.to_string()),
+        }
+    }
+
+    pub fn calls(&self) -> Vec<RecordedCall> {
+        self.calls
+            .lock()
+            .map(|calls| calls.clone())
+            .unwrap_or_default()
+    }
+
+    /// The argument vector of each recorded call, in order.
+    pub fn args(&self) -> Vec<Vec<String>> {
+        self.calls()
+            .into_iter()
+            .map(|call| call.command.args)
+            .collect()
+    }
+}
+
+impl ProcessRunner for RecordingRunner {
+    fn spawn(&self, command: &ToolCommand) -> io::Result<Box<dyn ToolProcess>> {
+        let mut calls = self
+            .calls
+            .lock()
+            .map_err(|_| io::Error::other("recording runner lock poisoned"))?;
+        calls.push(RecordedCall {
+            command: command.clone(),
+            stdin_bytes: 0,
+        });
+        Ok(Box::new(FakeProcess {
+            index: calls.len() - 1,
+            calls: self.calls.clone(),
+            stdin: command.stdin,
+            output: command.args.last().cloned(),
+            failure: self.failure.clone(),
+        }))
+    }
+}
+
+struct FakeProcess {
+    index: usize,
+    calls: Arc<Mutex<Vec<RecordedCall>>>,
+    stdin: bool,
+    output: Option<String>,
+    failure: Option<String>,
+}
+
+impl ToolProcess for FakeProcess {
+    fn id(&self) -> Option<u32> {
+        None
+    }
+
+    fn take_stdin(&mut self) -> Option<Box<dyn Write + Send>> {
+        if !std::mem::take(&mut self.stdin) {
+            return None;
+        }
+        Some(Box::new(CountingSink {
+            index: self.index,
+            calls: self.calls.clone(),
+        }))
+    }
+
+    fn take_stdout(&mut self) -> Option<Box<dyn Read + Send>> {
+        Some(Box::new(io::empty()))
+    }
+
+    fn take_stderr(&mut self) -> Option<Box<dyn Read + Send>> {
+        let stderr = self.failure.clone().unwrap_or_default();
+        Some(Box::new(Cursor::new(stderr.into_bytes())))
+    }
+
+    fn kill(&mut self) {}
+
+    fn wait(&mut self) -> io::Result<bool> {
+        if self.failure.is_some() {
+            return Ok(false);
+        }
+        // "-" is ffmpeg's null output, used by the first of two passes.
+        if let Some(output) = self.output.as_deref().filter(|output| *output != "-") {
+            fs::write(output, b"")?;
+        }
+        Ok(true)
+    }
+}
+
+struct CountingSink {
+    index: usize,
+    calls: Arc<Mutex<Vec<RecordedCall>>>,
+}
+
+impl Write for CountingSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Ok(mut calls) = self.calls.lock() {
+            calls[self.index].stdin_bytes += buf.len() as u64;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,342 @@
+/// swscale algorithms accepted by the scale filter's `flags`.
+const SCALE_ALGORITHMS: &[&str] = &[
+    "fast_bilinear",
+    "bilinear",
+    "bicubic",
+    "neighbor",
+    "area",
+    "bicublin",
+    "gauss",
+    "sinc",
+    "lanczos",
+    "spline",
+];
+
+/// How the scaled frame is fitted into a `max_width` x `max_height` box.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FitMode {
+    /// Fit inside the box, keeping the aspect ratio, and letterbox the rest.
+    Contain,
+    /// Fill the box, keeping the aspect ratio, and crop the overflow.
+    Cover,
+    /// Scale to the box exactly, ignoring the aspect ratio.
+    Stretch,
+}
+
+impl FitMode {
+    fn parse(value: Option<&str>) -> Result<Self, String> {
+        match value.map(|fit| fit.trim().to_lowercase()).as_deref() {
+            None | Some("") | Some("contain") => Ok(Self::Contain),
+            Some("cover") => Ok(Self::Cover),
+            Some("stretch") => Ok(Self::Stretch),
+            Some(other) => Err(format!(
+                "Unknown fit mode '{}'; expected contain, cover or stretch",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Resize {
+    pub max_width: Option<u32>,
+    pub max_height: Option<u32>,
+    /// Factor applied to the source size before fitting.
+    pub scale: f64,
+    pub fit: FitMode,
+    pub algorithm: String,
+}
+
+impl Resize {
+    pub fn parse(
+        max_width: Option<u32>,
+        max_height: Option<u32>,
+        scale: Option<f64>,
+        fit: Option<&str>,
+        algorithm: Option<&str>,
+    ) -> Result<Self, String> {
+        if max_width == Some(0) || max_height == Some(0) {
+            return Err("Maximum width and height must be at least 1 pixel".to_string());
+        }
+        let scale = scale.unwrap_or(1.0);
+        if !scale.is_finite() || scale <= 0.0 || scale > 8.0 {
+            return Err(format!("Scale {} is out of range (greater than 0, up to 8)", scale));
+        }
+        let algorithm = match algorithm.map(|value| value.trim().to_lowercase()) {
+            None => "bicubic".to_string(),
+            Some(value) if value.is_empty() => "bicubic".to_string(),
+            Some(value) if SCALE_ALGORITHMS.contains(&value.as_str()) => value,
+            Some(value) => {
+                return Err(format!(
+                    "Unknown scale algorithm '{}'; expected one of: {}",
+                    value,
+                    SCALE_ALGORITHMS.join(", ")
+                ))
+            }
+        };
+        Ok(Self {
+            max_width,
+            max_height,
+            scale,
+            fit: FitMode::parse(fit)?,
+            algorithm,
+        })
+    }
+
+    fn is_identity(&self) -> bool {
+        self.max_width.is_none() && self.max_height.is_none() && self.scale == 1.0
+    }
+
+    /// Works out the scale, crop and pad steps that take a `src_w` x `src_h` canvas to the
+    /// requested size. With `even` set every output dimension is a multiple of two.
+    pub fn plan(&self, src_w: usize, src_h: usize, even: bool) -> ResizePlan {
+        let (src_w, src_h) = (src_w.max(1) as f64, src_h.max(1) as f64);
+        if self.is_identity() {
+            // Nothing to scale; odd sizes are padded rather than resampled.
+            let (w, h) = (src_w as u32, src_h as u32);
+            let pad = (even && (w % 2 == 1 || h % 2 == 1)).then(|| (w + w % 2, h + h % 2));
+            return ResizePlan {
+                scale: None,
+                crop: None,
+                pad,
+            };
+        }
+
+        let (w, h) = (src_w * self.scale, src_h * self.scale);
+        let bound_w = self.max_width.map(|max| max as f64);
+        let bound_h = self.max_height.map(|max| max as f64);
+        let mut crop = None;
+        let mut pad = None;
+        let (scale_w, scale_h) = match (self.fit, bound_w, bound_h) {
+            (FitMode::Stretch, _, _) => (bound_w.unwrap_or(w), bound_h.unwrap_or(h)),
+            (FitMode::Cover, Some(box_w), Some(box_h)) => {
+                let factor = (box_w / w).max(box_h / h);
+                crop = Some((dimension(box_w, even), dimension(box_h, even)));
+                (w * factor, h * factor)
+            }
+            // Contain, or cover with a single bound, which has nothing to crop. Contain never
+            // upscales, and only letterboxes a frame that had to shrink to fit the box.
+            _ => {
+                let factor = bound_w
+                    .map_or(1.0, |box_w| box_w / w)
+                    .min(bound_h.map_or(1.0, |box_h| box_h / h));
+                let factor = if factor < 1.0 {
+                    if let (Some(box_w), Some(box_h)) = (bound_w, bound_h) {
+                        pad = Some((dimension(box_w, even), dimension(box_h, even)));
+                    }
+                    factor
+                } else {
+                    1.0
+                };
+                (w * factor, h * factor)
+            }
+        };
+
+        let mut scale = (dimension(scale_w, even), dimension(scale_h, even));
+        // Rounding to even can push the frame a pixel past the box it has to fit.
+        if let Some((box_w, box_h)) = pad {
+            scale = (scale.0.min(box_w), scale.1.min(box_h));
+            if scale == (box_w, box_h) {
+                pad = None;
+            }
+        }
+        if let Some((box_w, box_h)) = crop {
+            scale = (scale.0.max(box_w), scale.1.max(box_h));
+            if scale == (box_w, box_h) {
+                crop = None;
+            }
+        }
+        ResizePlan {
+            scale: Some(scale),
+            crop,
+            pad,
+        }
+    }
+}
+
+/// Rounds a computed size to whole pixels, or to whole pixel pairs for yuv output.
+fn dimension(value: f64, even: bool) -> u32 {
+    if even {
+        ((value / 2.0).round() as u32).max(1) * 2
+    } else {
+        (value.round() as u32).max(1)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResizePlan {
+    pub scale: Option<(u32, u32)>,
+    /// Centered crop applied after scaling.
+    pub crop: Option<(u32, u32)>,
+    /// Centered pad applied after scaling.
+    pub pad: Option<(u32, u32)>,
+}
+
+impl ResizePlan {
+    /// The filter steps for the plan, or `None` when the frame passes through untouched.
+    /// `pad_color` fills letterbox bars and is any ffmpeg color expression.
+    pub fn filter(&self, algorithm: &str, pad_color: &str) -> Option<String> {
+        let mut steps = Vec::new();
+        if let Some((w, h)) = self.scale {
+            steps.push(format!("scale={}:{}:flags={}", w, h, algorithm));
+        }
+        if let Some((w, h)) = self.crop {
+            steps.push(format!("crop={}:{}", w, h));
+        }
+        if let Some((w, h)) = self.pad {
+            steps.push(format!(
+                "pad={}:{}:(ow-iw)/2:(oh-ih)/2:color={}",
+                w, h, pad_color
+            ));
+        }
+        if steps.is_empty() {
+            None
+        } else {
+            Some(steps.join(","))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resize(max: (Option<u32>, Option<u32>), scale: Option<f64>, fit: &str) -> Resize {
+        Resize::parse(max.0, max.1, scale, Some(fit), None).unwrap()
+    }
+
+    fn plan(
+        scale: Option<(u32, u32)>,
+        crop: Option<(u32, u32)>,
+        pad: Option<(u32, u32)>,
+    ) -> ResizePlan {
+        ResizePlan { scale, crop, pad }
+    }
+
+    #[test]
+    fn plans_fit_the_box() {
+        let cases = [
+            // Untouched sizes only get padded to even.
+            (
+                resize((None, None), None, "contain"),
+                (100, 50),
+                true,
+                plan(None, None, None),
+            ),
+            (
+                resize((None, None), None, "contain"),
+                (101, 51),
+                true,
+                plan(None, None, Some((102, 52))),
+            ),
+            (
+                resize((None, None), None, "contain"),
+                (101, 51),
+                false,
+                plan(None, None, None),
+            ),
+            // Contain shrinks into the box and letterboxes.
+            (
+                resize((Some(50), Some(50)), None, "contain"),
+                (200, 100),
+                false,
+                plan(Some((50, 25)), None, Some((50, 50))),
+            ),
+            // 25 rows round to the nearest even count.
+            (
+                resize((Some(50), Some(50)), None, "contain"),
+                (200, 100),
+                true,
+                plan(Some((50, 26)), None, Some((50, 50))),
+            ),
+            // Contain never upscales, and a single bound has nothing to letterbox.
+            (
+                resize((Some(100), Some(100)), None, "contain"),
+                (40, 20),
+                true,
+                plan(Some((40, 20)), None, None),
+            ),
+            (
+                resize((Some(100), None), None, "contain"),
+                (300, 150),
+                true,
+                plan(Some((100, 50)), None, None),
+            ),
+            // Cover fills the box and crops the overflow.
+            (
+                resize((Some(50), Some(50)), None, "cover"),
+                (200, 100),
+                true,
+                plan(Some((100, 50)), Some((50, 50)), None),
+            ),
+            (
+                resize((Some(50), Some(50)), None, "cover"),
+                (100, 100),
+                true,
+                plan(Some((50, 50)), None, None),
+            ),
+            (
+                resize((Some(60), Some(90)), None, "stretch"),
+                (200, 100),
+                true,
+                plan(Some((60, 90)), None, None),
+            ),
+            // Scale applies before fitting.
+            (
+                resize((None, None), Some(0.5), "contain"),
+                (101, 51),
+                true,
+                plan(Some((50, 26)), None, None),
+            ),
+            (
+                resize((Some(100), None), Some(2.0), "contain"),
+                (80, 40),
+                false,
+                plan(Some((100, 50)), None, None),
+            ),
+        ];
+        for (resize, (w, h), even, expected) in cases {
+            assert_eq!(
+                resize.plan(w, h, even),
+                expected,
+                "{:?} {}x{}",
+                resize,
+                w,
+                h
+            );
+        }
+    }
+
+    #[test]
+    fn plan_filters_scale_then_crop_then_pad() {
+        assert_eq!(plan(None, None, None).filter("bicubic", "white"), None);
+        assert_eq!(
+            plan(Some((50, 26)), None, Some((50, 50))).filter("lanczos", "white"),
+            Some("scale=50:26:flags=lanczos,pad=50:50:(ow-iw)/2:(oh-ih)/2:color=white".to_string())
+        );
+        assert_eq!(
+            plan(Some((100, 50)), Some((50, 50)), None).filter("bicubic", "black"),
+            Some("scale=100:50:flags=bicubic,crop=50:50".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_checks_bounds_scale_and_names() {
+        let parsed = Resize::parse(None, None, None, None, Some(" Lanczos ")).unwrap();
+        assert_eq!(parsed.algorithm, "lanczos");
+        assert_eq!(parsed.fit, FitMode::Contain);
+        assert_eq!(
+            Resize::parse(None, None, None, None, None)
+                .unwrap()
+                .algorithm,
+            "bicubic"
+        );
+
+        assert!(Resize::parse(Some(0), None, None, None, None).is_err());
+        assert!(Resize::parse(None, None, Some(0.0), None, None).is_err());
+        assert!(Resize::parse(None, None, Some(8.5), None, None).is_err());
+        assert!(Resize::parse(None, None, Some(f64::NAN), None, None).is_err());
+        assert!(Resize::parse(None, None, None, Some("zoom"), None).is_err());
+        assert!(Resize::parse(None, None, None, None, Some("nearest")).is_err());
+    }
+}
@@ -0,0 +1,129 @@
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+
+use crate::jobs;
+
+/// An external tool invocation. stdout and stderr are always captured; stdin is only piped
+/// when the caller streams into it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolCommand {
+    pub program: PathBuf,
+    pub args: Vec<String>,
+    pub stdin: bool,
+}
+
+impl ToolCommand {
+    pub fn new(program: impl Into<PathBuf>) -> Self {
+        Self {
+            program: program.into(),
+            args: Vec::new(),
+            stdin: false,
+        }
+    }
+
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.args
+            .extend(args.into_iter().map(|arg| arg.as_ref().to_string()));
+        self
+    }
+
+    pub fn piped_stdin(mut self) -> Self {
+        self.stdin = true;
+        self
+    }
+}
+
+/// Starts external tools. The conversion engine never builds a `std::process::Command`
+/// itself, so tests can swap in a recording fake.
+pub trait ProcessRunner: Sync {
+    fn spawn(&self, command: &ToolCommand) -> io::Result<Box<dyn ToolProcess>>;
+}
+
+/// A started tool. Each pipe can be taken once.
+pub trait ToolProcess: Send {
+    /// OS process id, so cancelling a job can kill the process tree. Fakes have none.
+    fn id(&self) -> Option<u32>;
+    fn take_stdin(&mut self) -> Option<Box<dyn Write + Send>>;
+    fn take_stdout(&mut self) -> Option<Box<dyn Read + Send>>;
+    fn take_stderr(&mut self) -> Option<Box<dyn Read + Send>>;
+    fn kill(&mut self);
+    /// Blocks until the tool has exited without reaping it, so its pid cannot be reused
+    /// before `wait`. Processes whose pid stays reserved anyway return straight away.
+    fn wait_for_exit(&mut self) {}
+    /// Waits for the tool to exit and reports whether it succeeded.
+    fn wait(&mut self) -> io::Result<bool>;
+}
+
+/// Runs real binaries, each in its own process group.
+pub struct SystemRunner;
+
+impl ProcessRunner for SystemRunner {
+    fn spawn(&self, command: &ToolCommand) -> io::Result<Box<dyn ToolProcess>> {
+        let mut cmd = Command::new(&command.program);
+        jobs::isolate_process_group(&mut cmd)
+            .args(&command.args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if command.stdin {
+            cmd.stdin(Stdio::piped());
+        }
+        Ok(Box::new(SystemProcess(cmd.spawn()?)))
+    }
+}
+
+struct SystemProcess(Child);
+
+impl ToolProcess for SystemProcess {
+    fn id(&self) -> Option<u32> {
+        Some(self.0.id())
+    }
+
+    fn take_stdin(&mut self) -> Option<Box<dyn Write + Send>> {
+        self.0
+            .stdin
+            .take()
+            .map(|stdin| Box::new(stdin) as Box<dyn Write + Send>)
+    }
+
+    fn take_stdout(&mut self) -> Option<Box<dyn Read + Send>> {
+        self.0
+            .stdout
+            .take()
+            .map(|stdout| Box::new(stdout) as Box<dyn Read + Send>)
+    }
+
+    fn take_stderr(&mut self) -> Option<Box<dyn Read + Send>> {
+        self.0
+            .stderr
+            .take()
+            .map(|stderr| Box::new(stderr) as Box<dyn Read + Send>)
+    }
+
+    fn kill(&mut self) {
+        let _ = self.0.kill();
+    }
+
+    // On Windows the Child's open handle keeps the pid reserved until it is dropped.
+    #[cfg(unix)]
+    fn wait_for_exit(&mut self) {
+        let pid = self.0.id() as libc::id_t;
+        loop {
+            // WNOWAIT leaves the child a zombie, holding its pid until wait() reaps it.
+            let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+            let result =
+                unsafe { libc::waitid(libc::P_PID, pid, &mut info, libc::WEXITED | libc::WNOWAIT) };
+            if result == 0 || io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+                break;
+            }
+        }
+    }
+
+    fn wait(&mut self) -> io::Result<bool> {
+        self.0.wait().map(|status| status.success())
+    }
+}
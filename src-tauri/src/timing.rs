@@ -0,0 +1,460 @@
+use std::ops::Range;
+
+/// How composited frames map onto a constant-rate rawvideo stream.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameTiming {
+    /// Input frame rate for the stream, as an ffmpeg rational such as `1000/40`.
+    pub rate: String,
+    /// How many times each frame is written so it stays on screen for its duration.
+    pub repeats: Vec<usize>,
+}
+
+/// Plans per-frame repeat counts for `durations_ms`.
+///
+/// With an fps override every frame is shown for one tick, matching the old image-sequence
+/// input. Otherwise the tick is the largest step that divides every duration, but never
+/// finer than `min_tick_ms`, and rounding is applied to the running total so long
+/// animations do not drift.
+pub fn plan_frame_timing(durations_ms: &[u64], fps: Option<u32>, min_tick_ms: u64) -> FrameTiming {
+    if let Some(fps) = fps {
+        let repeats = if durations_ms.len() == 1 {
+            let ticks = (durations_ms[0] as f64 / 1000.0 * fps as f64).ceil();
+            vec![ticks.max(1.0) as usize]
+        } else {
+            vec![1; durations_ms.len()]
+        };
+        return FrameTiming {
+            rate: fps.to_string(),
+            repeats,
+        };
+    }
+
+    let tick_ms = durations_ms
+        .iter()
+        .copied()
+        .filter(|ms| *ms > 0)
+        .fold(0, gcd)
+        .max(min_tick_ms);
+    let mut repeats = Vec::with_capacity(durations_ms.len());
+    let mut elapsed_ms = 0;
+    let mut emitted = 0;
+    for duration_ms in durations_ms {
+        elapsed_ms += duration_ms;
+        let target = ((elapsed_ms as f64 / tick_ms as f64).round() as usize).max(emitted + 1);
+        repeats.push(target - emitted);
+        emitted = target;
+    }
+    FrameTiming {
+        rate: format!("1000/{}", tick_ms),
+        repeats,
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Upper bound on repeats, so a tiny clip and a long minimum duration cannot run away.
+const MAX_PLAYS: u32 = 1000;
+
+/// How many times a `clip_ms` frame sequence plays in the output.
+///
+/// `loops` is an explicit play count. With `use_source` the WebP's own loop count is used
+/// instead, where 0 (loop forever) counts as a single play. The count is then raised until
+/// the output lasts at least `min_duration_ms`.
+pub fn play_count(
+    loops: Option<u32>,
+    use_source: bool,
+    source_loops: u16,
+    clip_ms: u64,
+    min_duration_ms: u64,
+) -> u32 {
+    let plays = if use_source {
+        u32::from(source_loops.max(1))
+    } else {
+        loops.unwrap_or(1).max(1)
+    };
+    let needed = if clip_ms == 0 {
+        1
+    } else {
+        min_duration_ms.div_ceil(clip_ms).min(MAX_PLAYS as u64) as u32
+    };
+    plays.max(needed).min(MAX_PLAYS)
+}
+
+/// Browsers show frames of this length or shorter for [`BROWSER_CLAMPED_MS`] instead.
+const BROWSER_CLAMP_MAX_MS: u64 = 10;
+const BROWSER_CLAMPED_MS: u64 = 100;
+
+/// How source frame durations are normalized before anything else uses them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameDurations {
+    /// Stretch 0-10 ms frames to 100 ms, as browsers do when they play the WebP.
+    Browser,
+    /// Keep the durations stored in the file.
+    Raw,
+    /// Raise every frame to at least this many milliseconds.
+    Minimum(u64),
+}
+
+impl FrameDurations {
+    pub fn parse(value: Option<&str>, minimum_ms: Option<u64>) -> Result<Self, String> {
+        let policy = match value.map(|policy| policy.trim().to_lowercase()).as_deref() {
+            None | Some("") | Some("browser") => Self::Browser,
+            Some("raw") => Self::Raw,
+            Some("minimum") => match minimum_ms {
+                Some(ms) if ms > 0 => return Ok(Self::Minimum(ms)),
+                _ => {
+                    return Err(
+                        "The minimum frame timing needs a minimum frame duration of at least 1 ms"
+                            .to_string(),
+                    )
+                }
+            },
+            Some(other) => {
+                return Err(format!(
+                    "Unknown frame timing '{}'; expected browser, raw or minimum",
+                    other
+                ))
+            }
+        };
+        if minimum_ms.is_some() {
+            return Err(
+                "A minimum frame duration only applies to the minimum frame timing".to_string(),
+            );
+        }
+        Ok(policy)
+    }
+
+    pub fn normalize(self, duration_ms: u64) -> u64 {
+        match self {
+            Self::Browser if duration_ms <= BROWSER_CLAMP_MAX_MS => BROWSER_CLAMPED_MS,
+            Self::Browser | Self::Raw => duration_ms,
+            Self::Minimum(minimum_ms) => duration_ms.max(minimum_ms),
+        }
+    }
+
+    /// setpts step applying the same normalization to a decoded stream, or `None` for raw
+    /// timing. Each frame is placed after the previous one using the previous frame's
+    /// normalized length. The last frame keeps whatever length the decoder gave it, so clips
+    /// whose last frame needs normalizing have to be timed frame by frame instead.
+    pub fn filter(self) -> Option<String> {
+        let step = "PTS-PREV_INPTS";
+        let normalized = match self {
+            Self::Raw => return None,
+            Self::Browser => format!(
+                "if(lte({step},{}/TB),{}/TB,{step})",
+                BROWSER_CLAMP_MAX_MS as f64 / 1000.0,
+                BROWSER_CLAMPED_MS as f64 / 1000.0,
+                step = step
+            ),
+            Self::Minimum(minimum_ms) => {
+                format!("max({},{}/TB)", step, minimum_ms as f64 / 1000.0)
+            }
+        };
+        Some(format!(
+            "setpts='if(eq(N,0),PTS,PREV_OUTPTS+{})'",
+            normalized
+        ))
+    }
+}
+
+/// Order in which the animation's frames play.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Playback {
+    Forward,
+    Reverse,
+    /// Forward then back again, without repeating the turning frames, so it loops seamlessly.
+    PingPong,
+}
+
+impl Playback {
+    pub fn parse(value: Option<&str>) -> Result<Self, String> {
+        match value.map(|mode| mode.trim().to_lowercase()).as_deref() {
+            None | Some("") | Some("forward") => Ok(Self::Forward),
+            Some("reverse") => Ok(Self::Reverse),
+            Some("pingpong") | Some("ping-pong") | Some("boomerang") => Ok(Self::PingPong),
+            Some(other) => Err(format!(
+                "Unknown playback mode '{}'; expected forward, reverse or ping-pong",
+                other
+            )),
+        }
+    }
+
+    /// Source frame indices for one play through a `count`-frame animation.
+    pub fn frame_order(self, count: usize) -> Vec<usize> {
+        match self {
+            Self::Forward => (0..count).collect(),
+            Self::Reverse => (0..count).rev().collect(),
+            Self::PingPong => (0..count).chain((1..count.saturating_sub(1)).rev()).collect(),
+        }
+    }
+
+    /// ffmpeg filter steps giving the same order for a decoded `count`-frame stream, or `None`
+    /// for forward playback. `reverse` keeps the forward timestamps, so the durations only
+    /// come out right when every frame lasts the same.
+    pub fn filter(self, count: usize) -> Option<String> {
+        match self {
+            Self::Forward => None,
+            Self::Reverse => Some("reverse".to_string()),
+            Self::PingPong if count < 3 => None,
+            Self::PingPong => Some(format!(
+                "split[fwd][back];[back]reverse,trim=start_frame=1:end_frame={},setpts=PTS-STARTPTS[rev];[fwd][rev]concat=n=2:v=1:a=0",
+                count - 1
+            )),
+        }
+    }
+}
+
+/// Where a trim starts or ends, in source time or source frame index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrimPoint {
+    Ms(u64),
+    Frame(usize),
+}
+
+/// Source frames kept by a trim, as a half-open index range. Millisecond points keep the
+/// frames that start inside `[start, end)`, which is what ffmpeg's own trim filter keeps.
+pub fn trim_range(
+    durations_ms: &[u64],
+    start: Option<TrimPoint>,
+    end: Option<TrimPoint>,
+) -> Result<Range<usize>, String> {
+    let count = durations_ms.len();
+    let mut starts = Vec::with_capacity(count);
+    let mut elapsed_ms = 0;
+    for duration_ms in durations_ms {
+        starts.push(elapsed_ms);
+        elapsed_ms += duration_ms;
+    }
+    let index_at = |point: TrimPoint| match point {
+        TrimPoint::Frame(index) => index.min(count),
+        TrimPoint::Ms(ms) => starts.iter().position(|start| *start >= ms).unwrap_or(count),
+    };
+    let first = start.map_or(0, index_at);
+    let last = end.map_or(count, index_at);
+    if first >= last {
+        return Err(format!(
+            "Trim keeps no frames (frames {} to {} of {})",
+            first, last, count
+        ));
+    }
+    Ok(first..last)
+}
+
+/// Frame duration at `speed` times normal playback, never shorter than a millisecond.
+pub fn scale_duration(duration_ms: u64, speed: f64) -> u64 {
+    ((duration_ms as f64 / speed).round() as u64).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_timing_uses_the_largest_shared_tick() {
+        let cases: &[(&[u64], u64, &str, &[usize])] = &[
+            (&[40, 40, 40], 10, "1000/40", &[1, 1, 1]),
+            (&[100, 50, 150], 10, "1000/50", &[2, 1, 3]),
+            (&[20, 60], 20, "1000/20", &[1, 3]),
+            // No shared tick coarser than a millisecond: the minimum wins and 33 ms rounds to
+            // 30 ms. Rounding the running total keeps the clip at 170 of its 173 ms.
+            (&[33, 100, 40], 10, "1000/10", &[3, 10, 4]),
+            (&[30, 45], 20, "1000/20", &[2, 2]),
+            // Zero-length frames still get a tick, and an all-zero clip falls back to the
+            // minimum.
+            (&[0, 100], 10, "1000/100", &[1, 1]),
+            (&[0, 0, 0], 10, "1000/10", &[1, 1, 1]),
+        ];
+        for (durations, min_tick, rate, repeats) in cases {
+            let timing = plan_frame_timing(durations, None, *min_tick);
+            assert_eq!(timing.rate, *rate, "{:?}", durations);
+            assert_eq!(timing.repeats, *repeats, "{:?}", durations);
+        }
+    }
+
+    #[test]
+    fn an_fps_override_shows_each_frame_for_one_tick() {
+        let timing = plan_frame_timing(&[33, 100, 40], Some(24), 10);
+        assert_eq!(timing.rate, "24");
+        assert_eq!(timing.repeats, [1, 1, 1]);
+        // A single frame is held for its whole duration.
+        assert_eq!(plan_frame_timing(&[1000], Some(24), 10).repeats, [24]);
+        assert_eq!(plan_frame_timing(&[0], Some(24), 10).repeats, [1]);
+    }
+
+    #[test]
+    fn play_count_honours_loops_then_the_minimum_duration() {
+        // (loops, use_source, source_loops, clip_ms, min_duration_ms) -> plays
+        let cases = [
+            ((None, false, 0, 300, 0), 1),
+            ((Some(3), false, 5, 300, 0), 3),
+            ((Some(0), false, 0, 300, 0), 1),
+            ((Some(3), true, 5, 300, 0), 5),
+            // Looping forever counts as one play.
+            ((None, true, 0, 300, 0), 1),
+            ((Some(2), false, 0, 300, 1000), 4),
+            ((Some(5), false, 0, 300, 1000), 5),
+            ((None, false, 0, 0, 1000), 1),
+        ];
+        for ((loops, use_source, source_loops, clip_ms, min_ms), plays) in cases {
+            assert_eq!(
+                play_count(loops, use_source, source_loops, clip_ms, min_ms),
+                plays,
+                "{:?}",
+                (loops, use_source, source_loops, clip_ms, min_ms)
+            );
+        }
+    }
+
+    #[test]
+    fn play_count_is_capped() {
+        assert_eq!(play_count(Some(5000), false, 0, 100, 0), MAX_PLAYS);
+        assert_eq!(play_count(None, false, 0, 1, 60_000), MAX_PLAYS);
+        assert_eq!(play_count(None, true, u16::MAX, 100, 0), MAX_PLAYS);
+    }
+
+    #[test]
+    fn frame_durations_normalize_per_policy() {
+        let cases = [
+            (
+                FrameDurations::Browser,
+                [0, 10, 11, 100],
+                [100, 100, 11, 100],
+            ),
+            (FrameDurations::Raw, [0, 10, 11, 100], [0, 10, 11, 100]),
+            (
+                FrameDurations::Minimum(50),
+                [0, 10, 50, 100],
+                [50, 50, 50, 100],
+            ),
+        ];
+        for (policy, stored, normalized) in cases {
+            assert_eq!(
+                stored.map(|ms| policy.normalize(ms)),
+                normalized,
+                "{:?}",
+                policy
+            );
+        }
+    }
+
+    #[test]
+    fn frame_durations_parse_and_filter() {
+        assert_eq!(
+            FrameDurations::parse(None, None),
+            Ok(FrameDurations::Browser)
+        );
+        assert_eq!(
+            FrameDurations::parse(Some(" RAW "), None),
+            Ok(FrameDurations::Raw)
+        );
+        assert_eq!(
+            FrameDurations::parse(Some("minimum"), Some(40)),
+            Ok(FrameDurations::Minimum(40))
+        );
+        assert!(FrameDurations::parse(Some("minimum"), None).is_err());
+        assert!(FrameDurations::parse(Some("minimum"), Some(0)).is_err());
+        assert!(FrameDurations::parse(Some("browser"), Some(40)).is_err());
+        assert!(FrameDurations::parse(Some("gif"), None).is_err());
+
+        assert_eq!(FrameDurations::Raw.filter(), None);
+        assert_eq!(
+            FrameDurations::Browser.filter().unwrap(),
+            "setpts='if(eq(N,0),PTS,PREV_OUTPTS+if(lte(PTS-PREV_INPTS,0.01/TB),0.1/TB,PTS-PREV_INPTS))'"
+        );
+        assert_eq!(
+            FrameDurations::Minimum(40).filter().unwrap(),
+            "setpts='if(eq(N,0),PTS,PREV_OUTPTS+max(PTS-PREV_INPTS,0.04/TB))'"
+        );
+    }
+
+    #[test]
+    fn playback_orders_frames() {
+        let cases: &[(Playback, usize, &[usize])] = &[
+            (Playback::Forward, 4, &[0, 1, 2, 3]),
+            (Playback::Reverse, 4, &[3, 2, 1, 0]),
+            (Playback::PingPong, 4, &[0, 1, 2, 3, 2, 1]),
+            (Playback::PingPong, 3, &[0, 1, 2, 1]),
+            // Too short to turn around without repeating a frame.
+            (Playback::PingPong, 2, &[0, 1]),
+            (Playback::PingPong, 1, &[0]),
+            (Playback::Reverse, 0, &[]),
+        ];
+        for (playback, count, order) in cases {
+            assert_eq!(
+                playback.frame_order(*count),
+                *order,
+                "{:?} {}",
+                playback,
+                count
+            );
+        }
+    }
+
+    #[test]
+    fn playback_filters_match_the_frame_order() {
+        assert_eq!(Playback::Forward.filter(4), None);
+        assert_eq!(Playback::Reverse.filter(4).as_deref(), Some("reverse"));
+        assert_eq!(Playback::PingPong.filter(2), None);
+        assert_eq!(
+            Playback::PingPong.filter(4).unwrap(),
+            "split[fwd][back];[back]reverse,trim=start_frame=1:end_frame=3,setpts=PTS-STARTPTS[rev];[fwd][rev]concat=n=2:v=1:a=0"
+        );
+        assert_eq!(Playback::parse(Some("Boomerang")), Ok(Playback::PingPong));
+        assert_eq!(Playback::parse(None), Ok(Playback::Forward));
+        assert!(Playback::parse(Some("shuffle")).is_err());
+    }
+
+    #[test]
+    fn trim_keeps_frames_starting_inside_the_range() {
+        use TrimPoint::{Frame, Ms};
+        // Frames start at 0, 100, 150 and 300 ms.
+        let durations = [100, 50, 150, 100];
+        let cases = [
+            (None, None, 0..4),
+            (Some(Frame(1)), Some(Frame(3)), 1..3),
+            (Some(Frame(2)), Some(Frame(99)), 2..4),
+            (Some(Ms(100)), None, 1..4),
+            (Some(Ms(101)), None, 2..4),
+            (None, Some(Ms(150)), 0..2),
+            (None, Some(Ms(151)), 0..3),
+            (Some(Ms(50)), Some(Frame(3)), 1..3),
+        ];
+        for (start, end, kept) in cases {
+            assert_eq!(
+                trim_range(&durations, start, end),
+                Ok(kept),
+                "{:?}",
+                (start, end)
+            );
+        }
+    }
+
+    #[test]
+    fn trim_that_keeps_nothing_is_rejected() {
+        let durations = [100, 100];
+        assert!(trim_range(
+            &durations,
+            Some(TrimPoint::Frame(1)),
+            Some(TrimPoint::Frame(1))
+        )
+        .is_err());
+        assert!(trim_range(&durations, Some(TrimPoint::Ms(500)), None).is_err());
+        assert!(trim_range(&durations, None, Some(TrimPoint::Ms(0))).is_err());
+    }
+
+    #[test]
+    fn speed_scales_durations_down_to_a_millisecond() {
+        assert_eq!(scale_duration(100, 2.0), 50);
+        assert_eq!(scale_duration(100, 0.5), 200);
+        assert_eq!(scale_duration(100, 3.0), 33);
+        assert_eq!(scale_duration(25, 2.0), 13);
+        assert_eq!(scale_duration(1, 4.0), 1);
+        assert_eq!(scale_duration(0, 1.0), 1);
+    }
+}
@@ -0,0 +1,545 @@
+use crate::encode::VideoCodec;
+use crate::engine::AdvancedOptions;
+
+const QUALITY_PRESETS: &[&str] = &["high", "balanced", "small"];
+
+const X26X_PRESETS: &[&str] = &[
+    "ultrafast",
+    "superfast",
+    "veryfast",
+    "faster",
+    "fast",
+    "medium",
+    "slow",
+    "slower",
+    "veryslow",
+    "placebo",
+];
+const VP9_SPEEDS: &[&str] = &["0", "1", "2", "3", "4", "5", "6", "7", "8"];
+const SVT_AV1_PRESETS: &[&str] = &[
+    "0", "1", "2", "3", "4", "5", "6", "7", "8", "9", "10", "11", "12", "13",
+];
+
+// Profile tables pair each profile with the pixel formats it can encode. They are ordered by
+// preference, so the first profile that fits is the one picked when only pix_fmt is given.
+const H264_PROFILES: &[(&str, &[&str])] = &[
+    ("high", &["yuv420p"]),
+    ("high10", &["yuv420p", "yuv420p10le"]),
+    (
+        "high422",
+        &["yuv420p", "yuv422p", "yuv420p10le", "yuv422p10le"],
+    ),
+    (
+        "high444",
+        &[
+            "yuv420p",
+            "yuv422p",
+            "yuv444p",
+            "yuv420p10le",
+            "yuv422p10le",
+            "yuv444p10le",
+        ],
+    ),
+    ("main", &["yuv420p"]),
+    ("baseline", &["yuv420p"]),
+];
+const HEVC_PROFILES: &[(&str, &[&str])] = &[
+    ("main", &["yuv420p"]),
+    ("main10", &["yuv420p", "yuv420p10le"]),
+    ("main12", &["yuv420p", "yuv420p10le", "yuv420p12le"]),
+    ("main422-10", &["yuv420p", "yuv420p10le", "yuv422p10le"]),
+    ("main444-8", &["yuv420p", "yuv444p"]),
+    (
+        "main444-10",
+        &[
+            "yuv420p",
+            "yuv420p10le",
+            "yuv422p10le",
+            "yuv444p",
+            "yuv444p10le",
+        ],
+    ),
+];
+const VP9_PROFILES: &[(&str, &[&str])] = &[
+    ("0", &["yuva420p", "yuv420p"]),
+    ("1", &["yuv422p", "yuv444p"]),
+    ("2", &["yuv420p10le"]),
+    ("3", &["yuv422p10le", "yuv444p10le"]),
+];
+const PRORES_PROFILES: &[(&str, &[&str])] = &[
+    ("4444", &["yuva444p10le", "yuv444p10le"]),
+    ("4444xq", &["yuva444p10le", "yuv444p10le"]),
+];
+
+const H264_LEVELS: &[&str] = &[
+    "1", "1b", "1.1", "1.2", "1.3", "2", "2.1", "2.2", "3", "3.1", "3.2", "4", "4.1", "4.2", "5",
+    "5.1", "5.2", "6", "6.1", "6.2",
+];
+const HEVC_LEVELS: &[&str] = &[
+    "1", "2", "2.1", "3", "3.1", "4", "4.1", "5", "5.1", "5.2", "6", "6.1", "6.2",
+];
+
+const H264_TUNES: &[&str] = &[
+    "film",
+    "animation",
+    "grain",
+    "stillimage",
+    "fastdecode",
+    "zerolatency",
+    "psnr",
+    "ssim",
+];
+const HEVC_TUNES: &[&str] = &[
+    "animation",
+    "grain",
+    "fastdecode",
+    "zerolatency",
+    "psnr",
+    "ssim",
+];
+// libvpx calls this tune-content.
+const VP9_TUNES: &[&str] = &["default", "screen", "film"];
+
+/// What each encoder accepts. An empty list means the setting does not apply to the codec.
+struct Capabilities {
+    /// Highest CRF (or qscale for ProRes); `None` when the encoder has no quality scale.
+    crf_max: Option<u8>,
+    presets: &'static [&'static str],
+    profiles: &'static [(&'static str, &'static [&'static str])],
+    pix_fmts: &'static [&'static str],
+    levels: &'static [&'static str],
+    tunes: &'static [&'static str],
+    /// Intra-only encoders have no GOP to size.
+    keyframes: bool,
+}
+
+fn capabilities(codec: VideoCodec) -> Capabilities {
+    match codec {
+        VideoCodec::H264 => Capabilities {
+            crf_max: Some(51),
+            presets: X26X_PRESETS,
+            profiles: H264_PROFILES,
+            pix_fmts: &[
+                "yuv420p",
+                "yuv422p",
+                "yuv444p",
+                "yuv420p10le",
+                "yuv422p10le",
+                "yuv444p10le",
+            ],
+            levels: H264_LEVELS,
+            tunes: H264_TUNES,
+            keyframes: true,
+        },
+        VideoCodec::Hevc => Capabilities {
+            crf_max: Some(51),
+            presets: X26X_PRESETS,
+            profiles: HEVC_PROFILES,
+            pix_fmts: &[
+                "yuv420p",
+                "yuv444p",
+                "yuv420p10le",
+                "yuv422p10le",
+                "yuv444p10le",
+                "yuv420p12le",
+            ],
+            levels: HEVC_LEVELS,
+            tunes: HEVC_TUNES,
+            keyframes: true,
+        },
+        VideoCodec::Vp9 => Capabilities {
+            crf_max: Some(63),
+            presets: VP9_SPEEDS,
+            profiles: VP9_PROFILES,
+            pix_fmts: &[
+                "yuva420p",
+                "yuv420p",
+                "yuv422p",
+                "yuv444p",
+                "yuv420p10le",
+                "yuv422p10le",
+                "yuv444p10le",
+            ],
+            levels: &[],
+            tunes: VP9_TUNES,
+            keyframes: true,
+        },
+        VideoCodec::Av1 => Capabilities {
+            crf_max: Some(63),
+            presets: SVT_AV1_PRESETS,
+            profiles: &[],
+            pix_fmts: &["yuv420p", "yuv420p10le"],
+            levels: &[],
+            tunes: &[],
+            keyframes: true,
+        },
+        VideoCodec::ProRes4444 => Capabilities {
+            crf_max: Some(32),
+            presets: &[],
+            profiles: PRORES_PROFILES,
+            pix_fmts: &["yuva444p10le", "yuv444p10le"],
+            levels: &[],
+            tunes: &[],
+            keyframes: false,
+        },
+        VideoCodec::Qtrle => Capabilities {
+            crf_max: None,
+            presets: &[],
+            profiles: &[],
+            pix_fmts: &["argb", "rgb24", "rgb555be", "gray"],
+            levels: &[],
+            tunes: &[],
+            keyframes: true,
+        },
+    }
+}
+
+/// Encoder settings after the quality preset and any advanced overrides have been applied
+/// and checked against the codec.
+#[derive(Debug, Clone)]
+pub struct EncoderTuning {
+    /// CRF, or qscale for ProRes. Unused by QuickTime Animation.
+    pub crf: u8,
+    /// Encoder speed preset; empty for encoders without one.
+    pub preset: String,
+    pub profile: Option<String>,
+    pub level: Option<String>,
+    pub tune: Option<String>,
+    pub keyframe_interval: Option<u32>,
+    pub pix_fmt: String,
+}
+
+impl EncoderTuning {
+    /// Starts from the quality preset's values and applies the advanced overrides. Values the
+    /// codec does not accept are reported instead of being dropped.
+    pub fn resolve(
+        codec: VideoCodec,
+        quality: &str,
+        advanced: Option<&AdvancedOptions>,
+    ) -> Result<Self, String> {
+        if !QUALITY_PRESETS.contains(&quality) {
+            return Err(format!(
+                "Unknown quality preset '{}'; expected high, balanced or small",
+                quality
+            ));
+        }
+        let caps = capabilities(codec);
+        let (default_crf, default_preset) = codec.quality_params(quality);
+        let empty = AdvancedOptions::default();
+        let advanced = advanced.unwrap_or(&empty);
+
+        let crf = match (advanced.crf, caps.crf_max) {
+            (None, _) => default_crf,
+            (Some(_), None) => return Err(unsupported(codec, "crf")),
+            (Some(crf), Some(max)) if crf > max => {
+                return Err(format!(
+                    "CRF {} is out of range for the {} codec (0-{})",
+                    crf,
+                    codec.name(),
+                    max
+                ))
+            }
+            (Some(crf), Some(_)) => crf,
+        };
+
+        let preset = choose(codec, "preset", advanced.preset.as_deref(), caps.presets)?
+            .unwrap_or(default_preset);
+
+        let pix_fmt = choose(codec, "pix_fmt", advanced.pix_fmt.as_deref(), caps.pix_fmts)?
+            .unwrap_or_else(|| default_pix_fmt(codec).to_string());
+
+        let profile_names: Vec<&str> = caps.profiles.iter().map(|(name, _)| *name).collect();
+        let profile = match choose(
+            codec,
+            "profile",
+            advanced.profile.as_deref(),
+            &profile_names,
+        )? {
+            Some(profile) => {
+                if !profile_supports(caps.profiles, &profile, &pix_fmt) {
+                    return Err(format!(
+                        "Profile '{}' does not support pix_fmt '{}' with the {} codec",
+                        profile,
+                        pix_fmt,
+                        codec.name()
+                    ));
+                }
+                Some(profile)
+            }
+            // libvpx picks its own profile from the pixel format.
+            None if codec == VideoCodec::Vp9 => None,
+            None => caps
+                .profiles
+                .iter()
+                .find(|(_, pix_fmts)| pix_fmts.contains(&pix_fmt.as_str()))
+                .map(|(name, _)| name.to_string()),
+        };
+
+        let level = match choose(codec, "level", advanced.level.as_deref(), caps.levels)? {
+            Some(level) => Some(level),
+            None if codec == VideoCodec::H264 => Some("4.1".to_string()),
+            None => None,
+        };
+
+        // "none" clears the default tune rather than naming one.
+        let tune = match advanced
+            .tune
+            .as_deref()
+            .map(|tune| tune.trim().to_lowercase())
+        {
+            Some(tune) if tune == "none" => None,
+            _ => match choose(codec, "tune", advanced.tune.as_deref(), caps.tunes)? {
+                Some(tune) => Some(tune),
+                None if codec == VideoCodec::H264 => Some("animation".to_string()),
+                None => None,
+            },
+        };
+
+        let keyframe_interval = match advanced.keyframe_interval {
+            None => None,
+            Some(_) if !caps.keyframes => return Err(unsupported(codec, "keyframe interval")),
+            Some(0) => return Err("Keyframe interval must be at least 1 frame".to_string()),
+            Some(interval) => Some(interval),
+        };
+
+        Ok(Self {
+            crf,
+            preset,
+            profile,
+            level,
+            tune,
+            keyframe_interval,
+            pix_fmt,
+        })
+    }
+
+    /// Whether the pixel format carries alpha. Frames for formats without it are flattened
+    /// onto the background first.
+    pub fn keeps_alpha(&self) -> bool {
+        self.pix_fmt.starts_with("yuva") || self.pix_fmt == "argb"
+    }
+}
+
+fn default_pix_fmt(codec: VideoCodec) -> &'static str {
+    match codec {
+        // libvpx stores alpha as a second stream that WebM players composite.
+        VideoCodec::Vp9 => "yuva420p",
+        VideoCodec::ProRes4444 => "yuva444p10le",
+        VideoCodec::Qtrle => "argb",
+        _ => "yuv420p",
+    }
+}
+
+fn profile_supports(profiles: &[(&str, &[&str])], profile: &str, pix_fmt: &str) -> bool {
+    profiles
+        .iter()
+        .any(|(name, pix_fmts)| *name == profile && pix_fmts.contains(&pix_fmt))
+}
+
+/// Normalizes an optional setting and checks it against `allowed`. Blank values count as
+/// unset.
+fn choose(
+    codec: VideoCodec,
+    setting: &str,
+    value: Option<&str>,
+    allowed: &[&str],
+) -> Result<Option<String>, String> {
+    let Some(value) = value
+        .map(|value| value.trim().to_lowercase())
+        .filter(|value| !value.is_empty())
+    else {
+        return Ok(None);
+    };
+    if allowed.is_empty() {
+        return Err(unsupported(codec, setting));
+    }
+    if !allowed.contains(&value.as_str()) {
+        return Err(format!(
+            "Invalid {} '{}' for the {} codec; expected one of: {}",
+            setting,
+            value,
+            codec.name(),
+            allowed.join(", ")
+        ));
+    }
+    Ok(Some(value))
+}
+
+fn unsupported(codec: VideoCodec, setting: &str) -> String {
+    format!(
+        "The {} setting is not supported with the {} codec",
+        setting,
+        codec.name()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn resolve(codec: VideoCodec, advanced: serde_json::Value) -> Result<EncoderTuning, String> {
+        let advanced: AdvancedOptions = serde_json::from_value(advanced).unwrap();
+        EncoderTuning::resolve(codec, "high", Some(&advanced))
+    }
+
+    #[test]
+    fn defaults_follow_the_quality_preset() {
+        let tuning = EncoderTuning::resolve(VideoCodec::H264, "high", None).unwrap();
+        assert_eq!(tuning.profile.as_deref(), Some("high"));
+        assert_eq!(tuning.level.as_deref(), Some("4.1"));
+        assert_eq!(tuning.tune.as_deref(), Some("animation"));
+        assert_eq!(tuning.pix_fmt, "yuv420p");
+
+        let tuning = EncoderTuning::resolve(VideoCodec::ProRes4444, "small", None).unwrap();
+        assert_eq!(tuning.profile.as_deref(), Some("4444"));
+        assert_eq!(tuning.pix_fmt, "yuva444p10le");
+        assert!(tuning.keeps_alpha());
+
+        let tuning = EncoderTuning::resolve(VideoCodec::Vp9, "high", None).unwrap();
+        assert_eq!(tuning.profile, None);
+        assert_eq!(tuning.pix_fmt, "yuva420p");
+        assert!(tuning.keeps_alpha());
+        assert!(!resolve(VideoCodec::Vp9, json!({ "pixFmt": "yuv420p" }))
+            .unwrap()
+            .keeps_alpha());
+
+        assert!(EncoderTuning::resolve(VideoCodec::H264, "ultra", None).is_err());
+    }
+
+    #[test]
+    fn each_codec_accepts_its_own_settings() {
+        let cases = [
+            (
+                VideoCodec::H264,
+                json!({ "crf": 51, "preset": "VerySlow", "profile": "high10", "pixFmt": "yuv420p10le",
+                        "level": "5.1", "tune": "film", "keyframeInterval": 60 }),
+            ),
+            (
+                VideoCodec::Hevc,
+                json!({ "crf": 0, "preset": "fast", "profile": "main10", "pixFmt": "yuv420p10le",
+                        "level": "5", "tune": "grain", "keyframeInterval": 1 }),
+            ),
+            (
+                VideoCodec::Vp9,
+                json!({ "crf": 63, "preset": "4", "profile": "2", "pixFmt": "yuv420p10le",
+                        "tune": "screen", "keyframeInterval": 120 }),
+            ),
+            (
+                VideoCodec::Av1,
+                json!({ "crf": 63, "preset": "13", "pixFmt": "yuv420p10le", "keyframeInterval": 240 }),
+            ),
+            (
+                VideoCodec::ProRes4444,
+                json!({ "crf": 32, "profile": "4444xq", "pixFmt": "yuv444p10le" }),
+            ),
+            (
+                VideoCodec::Qtrle,
+                json!({ "pixFmt": "rgb24", "keyframeInterval": 30 }),
+            ),
+        ];
+        for (codec, advanced) in cases {
+            let expected: AdvancedOptions = serde_json::from_value(advanced.clone()).unwrap();
+            let tuning = resolve(codec, advanced).unwrap_or_else(|e| panic!("{:?}: {}", codec, e));
+            if let Some(crf) = expected.crf {
+                assert_eq!(tuning.crf, crf, "{:?}", codec);
+            }
+            if let Some(preset) = expected.preset {
+                assert_eq!(tuning.preset, preset.to_lowercase(), "{:?}", codec);
+            }
+            assert_eq!(tuning.profile, expected.profile, "{:?}", codec);
+            assert_eq!(Some(tuning.pix_fmt), expected.pix_fmt, "{:?}", codec);
+            assert_eq!(
+                tuning.keyframe_interval, expected.keyframe_interval,
+                "{:?}",
+                codec
+            );
+            if codec != VideoCodec::H264 {
+                assert_eq!(tuning.level, expected.level, "{:?}", codec);
+                assert_eq!(tuning.tune, expected.tune, "{:?}", codec);
+            }
+        }
+    }
+
+    #[test]
+    fn each_codec_rejects_settings_it_cannot_use() {
+        let cases = [
+            (VideoCodec::H264, json!({ "crf": 52 })),
+            (VideoCodec::H264, json!({ "preset": "turbo" })),
+            (
+                VideoCodec::H264,
+                json!({ "profile": "high", "pixFmt": "yuv444p" }),
+            ),
+            (VideoCodec::H264, json!({ "level": "7" })),
+            (VideoCodec::H264, json!({ "tune": "screen" })),
+            (VideoCodec::H264, json!({ "keyframeInterval": 0 })),
+            (VideoCodec::Hevc, json!({ "crf": 52 })),
+            (
+                VideoCodec::Hevc,
+                json!({ "profile": "main", "pixFmt": "yuv420p10le" }),
+            ),
+            (VideoCodec::Hevc, json!({ "level": "4.2" })),
+            (VideoCodec::Hevc, json!({ "tune": "film" })),
+            (VideoCodec::Vp9, json!({ "crf": 64 })),
+            (VideoCodec::Vp9, json!({ "preset": "9" })),
+            (
+                VideoCodec::Vp9,
+                json!({ "profile": "1", "pixFmt": "yuv420p" }),
+            ),
+            (VideoCodec::Vp9, json!({ "level": "4.1" })),
+            (VideoCodec::Vp9, json!({ "tune": "animation" })),
+            (VideoCodec::Av1, json!({ "crf": 64 })),
+            (VideoCodec::Av1, json!({ "preset": "14" })),
+            (VideoCodec::Av1, json!({ "profile": "main" })),
+            (VideoCodec::Av1, json!({ "pixFmt": "yuv444p" })),
+            (VideoCodec::Av1, json!({ "tune": "film" })),
+            (VideoCodec::ProRes4444, json!({ "crf": 33 })),
+            (VideoCodec::ProRes4444, json!({ "preset": "slow" })),
+            (VideoCodec::ProRes4444, json!({ "profile": "422" })),
+            (VideoCodec::ProRes4444, json!({ "keyframeInterval": 30 })),
+            (VideoCodec::Qtrle, json!({ "crf": 10 })),
+            (VideoCodec::Qtrle, json!({ "pixFmt": "yuv420p" })),
+            (VideoCodec::Qtrle, json!({ "level": "1" })),
+        ];
+        for (codec, advanced) in cases {
+            assert!(
+                resolve(codec, advanced.clone()).is_err(),
+                "{:?} {}",
+                codec,
+                advanced
+            );
+        }
+    }
+
+    #[test]
+    fn profile_follows_pix_fmt_when_only_pix_fmt_is_given() {
+        let cases = [
+            (VideoCodec::H264, "yuv420p10le", Some("high10")),
+            (VideoCodec::H264, "yuv422p", Some("high422")),
+            (VideoCodec::H264, "yuv444p10le", Some("high444")),
+            (VideoCodec::Hevc, "yuv420p12le", Some("main12")),
+            (VideoCodec::Hevc, "yuv444p", Some("main444-8")),
+            // libvpx picks its own.
+            (VideoCodec::Vp9, "yuv444p", None),
+            (VideoCodec::ProRes4444, "yuv444p10le", Some("4444")),
+        ];
+        for (codec, pix_fmt, profile) in cases {
+            let tuning = resolve(codec, json!({ "pixFmt": pix_fmt })).unwrap();
+            assert_eq!(
+                tuning.profile.as_deref(),
+                profile,
+                "{:?} {}",
+                codec,
+                pix_fmt
+            );
+        }
+    }
+
+    #[test]
+    fn tune_none_clears_the_default() {
+        let tuning = resolve(VideoCodec::H264, json!({ "tune": "None" })).unwrap();
+        assert_eq!(tuning.tune, None);
+        let tuning = resolve(VideoCodec::H264, json!({ "tune": " " })).unwrap();
+        assert_eq!(tuning.tune.as_deref(), Some("animation"));
+    }
+}
@@ -0,0 +1,575 @@
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+use image::{ImageFormat, Rgba, RgbaImage};
+use serde::Serialize;
+
+const VP8X_FLAG_ICC: u8 = 0x20;
+const VP8X_FLAG_ALPHA: u8 = 0x10;
+const VP8X_FLAG_EXIF: u8 = 0x08;
+const VP8X_FLAG_XMP: u8 = 0x04;
+const VP8X_FLAG_ANIMATION: u8 = 0x02;
+
+const ANMF_FLAG_NO_BLEND: u8 = 0x02;
+const ANMF_FLAG_DISPOSE_BACKGROUND: u8 = 0x01;
+
+/// Container-level description of a WebP file, read straight from its RIFF chunks.
+#[derive(Debug, Clone)]
+pub struct WebpInfo {
+    pub canvas_width: usize,
+    pub canvas_height: usize,
+    pub animated: bool,
+    pub has_alpha: bool,
+    pub has_icc: bool,
+    pub has_exif: bool,
+    pub has_xmp: bool,
+    /// Number of times the animation plays; 0 means forever.
+    pub loop_count: u16,
+    /// ANIM background color, converted from the on-disk BGRA order.
+    pub background_color: Rgba<u8>,
+    pub frames: Vec<FrameInfo>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FrameInfo {
+    pub offset_x: usize,
+    pub offset_y: usize,
+    pub width: usize,
+    pub height: usize,
+    pub duration_ms: u64,
+    pub dispose_background: bool,
+    pub blend: bool,
+    /// VP8L bitstream rather than lossy VP8.
+    pub lossless: bool,
+    /// Byte range of the frame's bitstream chunks (ALPH + VP8, or VP8L) within the file.
+    pub data: Range<usize>,
+}
+
+impl Default for FrameInfo {
+    fn default() -> Self {
+        Self {
+            offset_x: 0,
+            offset_y: 0,
+            width: 0,
+            height: 0,
+            duration_ms: 33,
+            dispose_background: false,
+            blend: true,
+            lossless: false,
+            data: 0..0,
+        }
+    }
+}
+
+/// Everything worth knowing about a file before converting it.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebpProbe {
+    pub width: usize,
+    pub height: usize,
+    pub animated: bool,
+    pub frame_count: usize,
+    /// Total of `frame_durations_ms` as stored in the file; 0 for a still image.
+    pub duration_ms: u64,
+    /// Stored per-frame durations, before any browser-style normalization. Empty for a
+    /// still image.
+    pub frame_durations_ms: Vec<u64>,
+    /// Number of times the animation plays; 0 means forever.
+    pub loop_count: u16,
+    pub has_alpha: bool,
+    pub compression: Compression,
+    pub has_icc: bool,
+    pub has_exif: bool,
+    pub has_xmp: bool,
+    pub file_size: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Lossy,
+    Lossless,
+    /// Animations whose frames use both.
+    Mixed,
+}
+
+struct Chunk<'a> {
+    fourcc: [u8; 4],
+    /// Offset of the chunk header within the file.
+    start: usize,
+    payload: &'a [u8],
+}
+
+impl Chunk<'_> {
+    fn end(&self) -> usize {
+        self.start + 8 + padded_len(self.payload.len())
+    }
+}
+
+pub fn read_info(path: &Path) -> Result<WebpInfo, String> {
+    let data = fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    parse(&data)
+}
+
+pub fn parse(data: &[u8]) -> Result<WebpInfo, String> {
+    if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WEBP" {
+        return Err("Not a RIFF/WebP file".to_string());
+    }
+    let riff_size = read_u32(&data[4..8]) as usize;
+    // Trailing bytes past the RIFF payload are ignored. A truncated file keeps its last chunk
+    // cut short, so ffmpeg still gets to try whatever frames are complete.
+    let end = (riff_size + 8).min(data.len());
+    let chunks = read_chunks(data, 12, end);
+
+    let first = chunks
+        .first()
+        .ok_or_else(|| "WebP file has no chunks".to_string())?;
+    match &first.fourcc {
+        b"VP8X" => parse_extended(data, &chunks),
+        b"VP8 " | b"VP8L" => {
+            let (width, height, has_alpha) = bitstream_dimensions(first)?;
+            Ok(WebpInfo {
+                canvas_width: width,
+                canvas_height: height,
+                animated: false,
+                has_alpha,
+                has_icc: false,
+                has_exif: false,
+                has_xmp: false,
+                loop_count: 0,
+                background_color: Rgba([0, 0, 0, 0]),
+                frames: vec![FrameInfo {
+                    width,
+                    height,
+                    lossless: &first.fourcc == b"VP8L",
+                    data: first.start..first.end().min(data.len()),
+                    ..FrameInfo::default()
+                }],
+            })
+        }
+        other => Err(format!(
+            "Unexpected first WebP chunk '{}'",
+            String::from_utf8_lossy(other)
+        )),
+    }
+}
+
+pub fn probe(path: &Path) -> Result<WebpProbe, String> {
+    let data = fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let info = parse(&data)?;
+    let frame_durations_ms: Vec<u64> = if info.animated {
+        info.frames.iter().map(|frame| frame.duration_ms).collect()
+    } else {
+        Vec::new()
+    };
+    let lossless = info.frames.iter().filter(|frame| frame.lossless).count();
+    let compression = if lossless == 0 {
+        Compression::Lossy
+    } else if lossless == info.frames.len() {
+        Compression::Lossless
+    } else {
+        Compression::Mixed
+    };
+    Ok(WebpProbe {
+        width: info.canvas_width,
+        height: info.canvas_height,
+        animated: info.animated,
+        frame_count: info.frames.len(),
+        duration_ms: frame_durations_ms.iter().sum(),
+        frame_durations_ms,
+        loop_count: info.loop_count,
+        has_alpha: info.has_alpha,
+        compression,
+        has_icc: info.has_icc,
+        has_exif: info.has_exif,
+        has_xmp: info.has_xmp,
+        file_size: data.len() as u64,
+    })
+}
+
+/// Decodes one frame's bitstream from the file bytes it was parsed from.
+pub fn decode_frame(data: &[u8], frame: &FrameInfo) -> Result<RgbaImage, String> {
+    let bitstream = data
+        .get(frame.data.clone())
+        .ok_or_else(|| "Frame data lies outside the file".to_string())?;
+
+    // Rewrap the frame chunks as a standalone still so the regular WebP decoder accepts them.
+    // A separate ALPH chunk is only valid inside an extended (VP8X) file.
+    let mut body = b"WEBP".to_vec();
+    if bitstream.starts_with(b"ALPH") {
+        let mut vp8x = vec![VP8X_FLAG_ALPHA, 0, 0, 0];
+        vp8x.extend_from_slice(&write_u24(frame.width.saturating_sub(1) as u32));
+        vp8x.extend_from_slice(&write_u24(frame.height.saturating_sub(1) as u32));
+        body.extend_from_slice(b"VP8X");
+        body.extend_from_slice(&(vp8x.len() as u32).to_le_bytes());
+        body.extend_from_slice(&vp8x);
+    }
+    body.extend_from_slice(bitstream);
+    let mut still = b"RIFF".to_vec();
+    still.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    still.extend_from_slice(&body);
+
+    image::load_from_memory_with_format(&still, ImageFormat::WebP)
+        .map(|img| img.to_rgba8())
+        .map_err(|e| e.to_string())
+}
+
+fn parse_extended(data: &[u8], chunks: &[Chunk]) -> Result<WebpInfo, String> {
+    let header = chunks[0].payload;
+    if header.len() < 10 {
+        return Err("VP8X chunk is too short".to_string());
+    }
+    let flags = header[0];
+    let mut info = WebpInfo {
+        canvas_width: read_u24(&header[4..7]) as usize + 1,
+        canvas_height: read_u24(&header[7..10]) as usize + 1,
+        animated: flags & VP8X_FLAG_ANIMATION != 0,
+        has_alpha: flags & VP8X_FLAG_ALPHA != 0,
+        has_icc: flags & VP8X_FLAG_ICC != 0,
+        has_exif: flags & VP8X_FLAG_EXIF != 0,
+        has_xmp: flags & VP8X_FLAG_XMP != 0,
+        loop_count: 0,
+        background_color: Rgba([0, 0, 0, 0]),
+        frames: Vec::new(),
+    };
+
+    // Still image bitstream chunks (ALPH followed by VP8, or a lone VP8L) at the top level.
+    let mut still_start: Option<usize> = None;
+
+    for chunk in &chunks[1..] {
+        match &chunk.fourcc {
+            b"ICCP" => info.has_icc = true,
+            b"EXIF" => info.has_exif = true,
+            b"XMP " => info.has_xmp = true,
+            b"ANIM" => {
+                if chunk.payload.len() < 6 {
+                    return Err("ANIM chunk is too short".to_string());
+                }
+                let bgra = &chunk.payload[0..4];
+                info.background_color = Rgba([bgra[2], bgra[1], bgra[0], bgra[3]]);
+                info.loop_count = u16::from_le_bytes([chunk.payload[4], chunk.payload[5]]);
+                info.animated = true;
+            }
+            b"ANMF" => {
+                info.animated = true;
+                info.frames.push(parse_anmf(data, chunk)?);
+            }
+            b"ALPH" => {
+                still_start.get_or_insert(chunk.start);
+            }
+            b"VP8 " | b"VP8L" => {
+                let start = *still_start.get_or_insert(chunk.start);
+                if info.frames.is_empty() {
+                    info.frames.push(FrameInfo {
+                        width: info.canvas_width,
+                        height: info.canvas_height,
+                        lossless: &chunk.fourcc == b"VP8L",
+                        data: start..chunk.end().min(data.len()),
+                        ..FrameInfo::default()
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if info.frames.is_empty() {
+        return Err("WebP file does not contain any frames".to_string());
+    }
+    Ok(info)
+}
+
+fn parse_anmf(data: &[u8], chunk: &Chunk) -> Result<FrameInfo, String> {
+    let payload = chunk.payload;
+    if payload.len() < 16 {
+        return Err("ANMF chunk is too short".to_string());
+    }
+    let flags = payload[15];
+    let sub_start = chunk.start + 8 + 16;
+    let sub_end = chunk.start + 8 + payload.len();
+    let sub_chunks = read_chunks(data, sub_start, sub_end);
+    let bitstream = sub_chunks
+        .iter()
+        .find(|sub| &sub.fourcc == b"VP8 " || &sub.fourcc == b"VP8L")
+        .ok_or_else(|| "ANMF frame has no VP8/VP8L bitstream".to_string())?;
+    let alpha_start = sub_chunks
+        .iter()
+        .find(|sub| &sub.fourcc == b"ALPH")
+        .map(|sub| sub.start);
+
+    Ok(FrameInfo {
+        offset_x: read_u24(&payload[0..3]) as usize * 2,
+        offset_y: read_u24(&payload[3..6]) as usize * 2,
+        width: read_u24(&payload[6..9]) as usize + 1,
+        height: read_u24(&payload[9..12]) as usize + 1,
+        duration_ms: read_u24(&payload[12..15]) as u64,
+        dispose_background: flags & ANMF_FLAG_DISPOSE_BACKGROUND != 0,
+        blend: flags & ANMF_FLAG_NO_BLEND == 0,
+        lossless: &bitstream.fourcc == b"VP8L",
+        data: alpha_start.unwrap_or(bitstream.start)..bitstream.end().min(sub_end),
+    })
+}
+
+/// Splits `data[start..end]` into chunks. A chunk running past `end` is kept with the payload
+/// that is there and ends the list.
+fn read_chunks(data: &[u8], start: usize, end: usize) -> Vec<Chunk<'_>> {
+    let mut chunks = Vec::new();
+    let mut pos = start;
+    while pos + 8 <= end {
+        let mut fourcc = [0u8; 4];
+        fourcc.copy_from_slice(&data[pos..pos + 4]);
+        let size = read_u32(&data[pos + 4..pos + 8]) as usize;
+        let payload_start = pos + 8;
+        let payload_end = payload_start.saturating_add(size);
+        chunks.push(Chunk {
+            fourcc,
+            start: pos,
+            payload: &data[payload_start..payload_end.min(end)],
+        });
+        if payload_end > end {
+            break;
+        }
+        pos = payload_start + padded_len(size);
+    }
+    chunks
+}
+
+fn bitstream_dimensions(chunk: &Chunk) -> Result<(usize, usize, bool), String> {
+    let payload = chunk.payload;
+    match &chunk.fourcc {
+        b"VP8 " => {
+            if payload.len() < 10 || payload[3..6] != [0x9d, 0x01, 0x2a] {
+                return Err("Invalid VP8 frame header".to_string());
+            }
+            let width = u16::from_le_bytes([payload[6], payload[7]]) & 0x3fff;
+            let height = u16::from_le_bytes([payload[8], payload[9]]) & 0x3fff;
+            Ok((width as usize, height as usize, false))
+        }
+        b"VP8L" => {
+            if payload.len() < 5 || payload[0] != 0x2f {
+                return Err("Invalid VP8L header".to_string());
+            }
+            let bits = read_u32(&payload[1..5]);
+            let width = (bits & 0x3fff) + 1;
+            let height = ((bits >> 14) & 0x3fff) + 1;
+            let has_alpha = (bits >> 28) & 1 == 1;
+            Ok((width as usize, height as usize, has_alpha))
+        }
+        _ => Err("Chunk is not a VP8/VP8L bitstream".to_string()),
+    }
+}
+
+fn padded_len(len: usize) -> usize {
+    len + (len & 1)
+}
+
+fn read_u24(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0])
+}
+
+fn write_u24(value: u32) -> [u8; 3] {
+    let bytes = value.to_le_bytes();
+    [bytes[0], bytes[1], bytes[2]]
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{
+        animation, chunk, frame, lossy_frame, riff, still, FLAG_DISPOSE, FLAG_NO_BLEND,
+        LOSSY_ALPHA, LOSSY_ALPHA_BITSTREAM,
+    };
+
+    /// LOSSY_ALPHA's VP8 chunk, which follows its 195-byte ALPH chunk.
+    fn lossy_vp8() -> &'static [u8] {
+        &LOSSY_ALPHA[LOSSY_ALPHA_BITSTREAM + 8 + 196..]
+    }
+
+    /// A VP8X header chunk with the given flags and canvas size.
+    fn vp8x(flags: u8, width: u32, height: u32) -> Vec<u8> {
+        let mut payload = vec![flags, 0, 0, 0];
+        payload.extend_from_slice(&write_u24(width - 1));
+        payload.extend_from_slice(&write_u24(height - 1));
+        chunk(b"VP8X", &payload)
+    }
+
+    /// Writes `file` to a fresh temp path and probes it.
+    fn probe_bytes(name: &str, file: &[u8]) -> WebpProbe {
+        let path = std::env::temp_dir().join(format!("{}-{}.webp", name, std::process::id()));
+        fs::write(&path, file).unwrap();
+        let probe = probe(&path);
+        let _ = fs::remove_file(&path);
+        probe.unwrap()
+    }
+
+    #[test]
+    fn probe_reports_frame_timing_and_container_details() {
+        let red = Rgba([255, 0, 0, 255]);
+        let mut second = frame(2, 2, red, 2, 0, 0);
+        second.duration_ms = 40;
+        let file = animation(4, 2, red, &[frame(4, 2, red, 0, 0, 0), second]);
+        let probe = probe_bytes("probe-animation", &file);
+        assert_eq!((probe.width, probe.height), (4, 2));
+        assert!(probe.animated);
+        assert_eq!(probe.frame_count, 2);
+        assert_eq!(probe.frame_durations_ms, [100, 40]);
+        assert_eq!(probe.duration_ms, 140);
+        assert_eq!(probe.loop_count, 0);
+        assert!(probe.has_alpha);
+        assert_eq!(probe.compression, Compression::Lossless);
+        assert!(!probe.has_icc && !probe.has_exif && !probe.has_xmp);
+        assert_eq!(probe.file_size, file.len() as u64);
+    }
+
+    #[test]
+    fn probe_of_a_still_has_no_timing() {
+        let file = still(&RgbaImage::from_pixel(3, 5, Rgba([0, 0, 255, 255])));
+        let probe = probe_bytes("probe-still", &file);
+        assert_eq!((probe.width, probe.height), (3, 5));
+        assert!(!probe.animated);
+        assert_eq!(probe.frame_count, 1);
+        assert!(probe.frame_durations_ms.is_empty());
+        assert_eq!(probe.duration_ms, 0);
+        assert_eq!(probe.compression, Compression::Lossless);
+    }
+
+    #[test]
+    fn simple_lossy_file_reads_the_vp8_frame_header() {
+        let mut body = b"WEBP".to_vec();
+        body.extend_from_slice(lossy_vp8());
+        let file = riff(body);
+        let info = parse(&file).unwrap();
+        assert_eq!((info.canvas_width, info.canvas_height), (16, 16));
+        assert!(!info.animated && !info.has_alpha);
+        assert_eq!(info.frames.len(), 1);
+        assert!(!info.frames[0].lossless);
+        assert_eq!(info.frames[0].data, 12..file.len());
+        let image = decode_frame(&file, &info.frames[0]).unwrap();
+        assert_eq!(image.dimensions(), (16, 16));
+    }
+
+    #[test]
+    fn simple_lossless_file_reads_the_vp8l_header() {
+        let image = RgbaImage::from_pixel(3, 5, Rgba([0, 0, 255, 128]));
+        let file = still(&image);
+        assert_eq!(&file[12..16], b"VP8L");
+        let info = parse(&file).unwrap();
+        assert_eq!((info.canvas_width, info.canvas_height), (3, 5));
+        assert!(info.has_alpha);
+        assert!(info.frames[0].lossless);
+        assert_eq!(info.frames[0].data, 12..file.len());
+        assert_eq!(decode_frame(&file, &info.frames[0]).unwrap(), image);
+    }
+
+    #[test]
+    fn extended_still_starts_its_frame_at_the_alph_chunk() {
+        let info = parse(LOSSY_ALPHA).unwrap();
+        assert_eq!((info.canvas_width, info.canvas_height), (16, 16));
+        assert!(info.has_alpha && !info.animated);
+        assert_eq!(info.frames.len(), 1);
+        assert!(!info.frames[0].lossless);
+        assert_eq!(
+            info.frames[0].data,
+            LOSSY_ALPHA_BITSTREAM..LOSSY_ALPHA.len()
+        );
+        let expected = image::load_from_memory(LOSSY_ALPHA).unwrap().to_rgba8();
+        assert_eq!(
+            decode_frame(LOSSY_ALPHA, &info.frames[0]).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn anmf_offsets_are_doubled_and_durations_are_24_bit() {
+        let red = Rgba([255, 0, 0, 255]);
+        let mut moved = frame(2, 2, red, 4, 2, FLAG_NO_BLEND | FLAG_DISPOSE);
+        moved.duration_ms = 0x01_2345;
+        let moved_image = moved.image.clone();
+        let file = animation(
+            8,
+            6,
+            Rgba([1, 2, 3, 4]),
+            &[frame(8, 6, red, 0, 0, 0), moved],
+        );
+        let info = parse(&file).unwrap();
+        assert!(info.animated);
+        assert_eq!(info.background_color, Rgba([1, 2, 3, 4]));
+        let first = &info.frames[0];
+        assert!(first.blend && !first.dispose_background);
+        let second = &info.frames[1];
+        assert_eq!((second.offset_x, second.offset_y), (4, 2));
+        assert_eq!((second.width, second.height), (2, 2));
+        assert_eq!(second.duration_ms, 74_565);
+        assert!(!second.blend && second.dispose_background);
+        assert_eq!(decode_frame(&file, second).unwrap(), moved_image);
+    }
+
+    #[test]
+    fn lossy_anmf_frames_include_their_alph_chunk() {
+        let file = animation(16, 16, Rgba([0, 0, 0, 0]), &[lossy_frame(0, 0, 0)]);
+        let info = parse(&file).unwrap();
+        let frame = &info.frames[0];
+        assert!(!frame.lossless);
+        assert_eq!(&file[frame.data.start..frame.data.start + 4], b"ALPH");
+        assert_eq!(frame.data.len(), LOSSY_ALPHA.len() - LOSSY_ALPHA_BITSTREAM);
+    }
+
+    #[test]
+    fn odd_length_chunks_are_padded_to_an_even_offset() {
+        let image = RgbaImage::from_pixel(2, 2, Rgba([0, 255, 0, 255]));
+        let vp8l = &still(&image)[12..];
+        let mut body = b"WEBP".to_vec();
+        body.extend(vp8x(VP8X_FLAG_XMP, 2, 2));
+        body.extend(chunk(b"XMP ", b"abc"));
+        let frame_start = 12 + body.len() - 4;
+        body.extend_from_slice(vp8l);
+        let file = riff(body);
+        let info = parse(&file).unwrap();
+        assert!(info.has_xmp);
+        assert_eq!(info.frames[0].data, frame_start..file.len());
+        assert_eq!(decode_frame(&file, &info.frames[0]).unwrap(), image);
+    }
+
+    #[test]
+    fn truncated_files_keep_what_is_there() {
+        let file = still(&RgbaImage::from_pixel(3, 5, Rgba([0, 0, 255, 255])));
+        let cut = &file[..file.len() - 3];
+        let info = parse(cut).unwrap();
+        assert_eq!((info.canvas_width, info.canvas_height), (3, 5));
+        assert_eq!(info.frames[0].data, 12..cut.len());
+
+        let red = Rgba([255, 0, 0, 255]);
+        let file = animation(
+            4,
+            4,
+            red,
+            &[frame(4, 4, red, 0, 0, 0), frame(2, 2, red, 0, 0, 0)],
+        );
+        let info = parse(&file[..file.len() - 3]).unwrap();
+        assert_eq!(info.frames.len(), 2);
+        assert_eq!(
+            decode_frame(&file, &info.frames[0]).unwrap().dimensions(),
+            (4, 4)
+        );
+
+        assert!(parse(&file[..12]).is_err());
+        assert!(parse(&file[..20]).is_err());
+    }
+
+    #[test]
+    fn other_formats_are_rejected() {
+        assert!(parse(b"").is_err());
+        assert!(parse(b"GIF89a\x01\x00\x01\x00\x00\x00\x00").is_err());
+        let mut wave = b"RIFF\x04\x00\x00\x00WAVE".to_vec();
+        wave.extend(chunk(b"fmt ", &[0; 16]));
+        assert!(parse(&wave).is_err());
+        let mut unknown = b"WEBP".to_vec();
+        unknown.extend(chunk(b"JUNK", &[0; 4]));
+        assert!(parse(&riff(unknown)).is_err());
+    }
+}
@@ -1,50 +1,211 @@
 import { invoke } from '@tauri-apps/api/core';
 import { listen } from '@tauri-apps/api/event';
 
+export type ConvertOptions = {
+  preset?: string | null;
+  outputDir?: string | null;
+  quality?: 'high' | 'balanced' | 'small';
+  fps?: number | null;
+  background?: string | null;
+  format?: 'mp4' | 'mov' | 'gif' | 'webm';
+  codec?: 'h264' | 'hevc' | 'vp9' | 'av1' | 'prores4444' | 'qtrle' | null;
+  gifDither?: 'bayer' | 'heckbert' | 'floyd_steinberg' | 'sierra2' | 'sierra2_4a' | 'sierra3' | 'burkes' | 'atkinson' | 'none' | null;
+  outputNameTemplate?: string | null;
+  sequence?: number | null;
+  staticDuration?: number | null;
+  targetSizeBytes?: number | null;
+  advanced?: AdvancedOptions | null;
+  maxWidth?: number | null;
+  maxHeight?: number | null;
+  scale?: number | null;
+  fit?: 'contain' | 'cover' | 'stretch' | null;
+  scaleAlgorithm?: 'fast_bilinear' | 'bilinear' | 'bicubic' | 'neighbor' | 'area' | 'bicublin' | 'gauss' | 'sinc' | 'lanczos' | 'spline' | null;
+  crop?: CropRect | null;
+  aspect?: '1:1' | '9:16' | '16:9' | (string & {}) | null;
+  aspectFill?: 'color' | 'blur' | null;
+  loops?: number | null;
+  useSourceLoops?: boolean | null;
+  minDuration?: number | null;
+  playback?: 'forward' | 'reverse' | 'ping-pong' | null;
+  canvasBackground?: 'anim' | 'transparent' | null;
+  frameTiming?: 'browser' | 'raw' | 'minimum' | null;
+  minFrameMs?: number | null;
+  trimStartMs?: number | null;
+  trimEndMs?: number | null;
+  trimStartFrame?: number | null;
+  trimEndFrame?: number | null;
+  speed?: number | null;
+};
+
+export type CropRect = {
+  x: number;
+  y: number;
+  width: number;
+  height: number;
+};
+
+export type Preset = {
+  name: string;
+  quality?: 'high' | 'balanced' | 'small' | null;
+  codec?: ConvertOptions['codec'];
+  crf?: number | null;
+  fps?: number | null;
+  background?: string | null;
+  outputFormat?: ConvertOptions['format'] | null;
+  outputNameTemplate?: string | null;
+  staticDuration?: number | null;
+};
+
+export type AdvancedOptions = {
+  crf?: number | null;
+  preset?: string | null;
+  profile?: string | null;
+  level?: string | null;
+  tune?: string | null;
+  keyframeInterval?: number | null;
+  pixFmt?: string | null;
+};
+
+export type ConversionResult = {
+  outputPath: string;
+  sizeBytes: number;
+  targetSizeBytes: number | null;
+  targetMet: boolean | null;
+};
+
+export type WebpProbe = {
+  width: number;
+  height: number;
+  animated: boolean;
+  frameCount: number;
+  durationMs: number;
+  frameDurationsMs: number[];
+  loopCount: number;
+  hasAlpha: boolean;
+  compression: 'lossy' | 'lossless' | 'mixed';
+  hasIcc: boolean;
+  hasExif: boolean;
+  hasXmp: boolean;
+  fileSize: number;
+};
+
+export type ConversionErrorCode =
+  | 'input_missing'
+  | 'invalid_options'
+  | 'tool_not_found'
+  | 'probe_failed'
+  | 'encoder_failed'
+  | 'fallback_failed'
+  | 'output_failed'
+  | 'disk_full'
+  | 'cancelled'
+  | 'internal';
+
+export type ConversionErrorPayload = {
+  code: ConversionErrorCode;
+  message: string;
+  logPath: string | null;
+  stderr: string | null;
+};
+
+export class ConversionError extends Error {
+  code: ConversionErrorCode;
+  logPath: string | null;
+  stderr: string | null;
+
+  constructor(payload: ConversionErrorPayload) {
+    super(payload.message);
+    this.name = 'ConversionError';
+    this.code = payload.code;
+    this.logPath = payload.logPath;
+    this.stderr = payload.stderr;
+  }
+}
+
+function isConversionErrorPayload(value: unknown): value is ConversionErrorPayload {
+  return typeof value === 'object' && value !== null && 'code' in value && 'message' in value;
+}
+
+export type QueueSnapshot = {
+  pending: string[];
+  running: string[];
+  maxParallel: number;
+};
+
+export type QueueEvent = {
+  job_id: string;
+  status: 'queued' | 'running' | 'done' | 'failed' | 'cancelled';
+  output_path: string | null;
+  size_bytes: number | null;
+  target_met: boolean | null;
+  error: ConversionErrorPayload | null;
+};
+
+function toBackendOptions(options: ConvertOptions) {
+  return {
+    preset: options.preset ?? null,
+    outputDir: options.outputDir ?? null,
+    quality: options.quality ?? null,
+    fps: options.fps ?? null,
+    background: options.background ?? null,
+    outputFormat: options.format ?? null,
+    codec: options.codec ?? null,
+    gifDither: options.gifDither ?? null,
+    outputNameTemplate: options.outputNameTemplate ?? null,
+    sequence: options.sequence ?? null,
+    staticDuration: options.staticDuration ?? null,
+    targetSizeBytes: options.targetSizeBytes ?? null,
+    advanced: options.advanced ?? null,
+    maxWidth: options.maxWidth ?? null,
+    maxHeight: options.maxHeight ?? null,
+    scale: options.scale ?? null,
+    fit: options.fit ?? null,
+    scaleAlgorithm: options.scaleAlgorithm ?? null,
+    crop: options.crop ?? null,
+    aspect: options.aspect ?? null,
+    aspectFill: options.aspectFill ?? null,
+    loops: options.loops ?? null,
+    useSourceLoops: options.useSourceLoops ?? null,
+    minDuration: options.minDuration ?? null,
+    playback: options.playback ?? null,
+    canvasBackground: options.canvasBackground ?? null,
+    frameTiming: options.frameTiming ?? null,
+    minFrameMs: options.minFrameMs ?? null,
+    trimStartMs: options.trimStartMs ?? null,
+    trimEndMs: options.trimEndMs ?? null,
+    trimStartFrame: options.trimStartFrame ?? null,
+    trimEndFrame: options.trimEndFrame ?? null,
+    speed: options.speed ?? null,
+  };
+}
+
 export async function convertWebPToMp4(
   inputPath: string,
   jobId: string,
-  options: {
-    outputDir?: string | null;
-    quality?: 'high' | 'balanced' | 'small';
-    fps?: number | null;
-    background?: string | null;
-    format?: 'mp4' | 'mov';
-    outputNameTemplate?: string | null;
-    sequence?: number | null;
-    staticDuration?: number | null;
-  },
-  onProgress?: (progress: number) => void
-): Promise<string> {
+  options: ConvertOptions,
+  onProgress?: (progress: number, etaSeconds: number | null) => void
+): Promise<ConversionResult> {
   let unlisten: (() => void) | null = null;
   try {
     if (onProgress) {
-      unlisten = await listen<{ job_id: string; progress: number }>('conversion-progress', (event) => {
+      unlisten = await listen<{ job_id: string; progress: number; eta_seconds?: number | null }>('conversion-progress', (event) => {
         if (event.payload?.job_id !== jobId) return;
         if (typeof event.payload?.progress === 'number') {
-          onProgress(event.payload.progress);
+          onProgress(event.payload.progress, event.payload.eta_seconds ?? null);
         }
       });
     }
 
-    const outputPath = await invoke<string>('convert_webp_to_mp4', {
+    return await invoke<ConversionResult>('convert_webp_to_mp4', {
       inputPath,
       jobId,
-      options: {
-        outputDir: options.outputDir ?? null,
-        quality: options.quality ?? 'high',
-        fps: options.fps ?? null,
-        background: options.background ?? null,
-        outputFormat: options.format ?? 'mp4',
-        outputNameTemplate: options.outputNameTemplate ?? null,
-        sequence: options.sequence ?? null,
-        staticDuration: options.staticDuration ?? null,
-      },
+      options: toBackendOptions(options),
     });
-
-    return outputPath;
   } catch (error) {
     console.error('Conversion failed:', error);
+    if (isConversionErrorPayload(error)) {
+      throw new ConversionError(error);
+    }
     if (typeof error === 'string') {
       throw new Error(error);
     }
@@ -58,3 +219,85 @@ export async function convertWebPToMp4(
     }
   }
 }
+
+export async function probeWebP(path: string): Promise<WebpProbe> {
+  try {
+    return await invoke<WebpProbe>('probe_webp', { path });
+  } catch (error) {
+    if (isConversionErrorPayload(error)) {
+      throw new ConversionError(error);
+    }
+    throw error;
+  }
+}
+
+export async function cancelConversion(jobId: string): Promise<boolean> {
+  return invoke<boolean>('cancel_conversion', { jobId });
+}
+
+export async function enqueueConversions(
+  jobs: { jobId: string; inputPath: string; options: ConvertOptions; priority?: number }[]
+): Promise<QueueSnapshot> {
+  return invoke<QueueSnapshot>('enqueue_conversions', {
+    jobs: jobs.map(job => ({
+      jobId: job.jobId,
+      inputPath: job.inputPath,
+      options: toBackendOptions(job.options),
+      priority: job.priority ?? null,
+    })),
+  });
+}
+
+export async function reorderQueue(jobIds: string[]): Promise<QueueSnapshot> {
+  return invoke<QueueSnapshot>('reorder_queue', { jobIds });
+}
+
+export async function setJobPriority(jobId: string, priority: number): Promise<boolean> {
+  return invoke<boolean>('set_job_priority', { jobId, priority });
+}
+
+export async function setMaxParallel(maxParallel: number): Promise<number> {
+  return invoke<number>('set_max_parallel', { maxParallel });
+}
+
+export async function getQueue(): Promise<QueueSnapshot> {
+  return invoke<QueueSnapshot>('get_queue');
+}
+
+async function invokePresetCommand<T>(command: string, args?: Record<string, unknown>): Promise<T> {
+  try {
+    return await invoke<T>(command, args);
+  } catch (error) {
+    if (isConversionErrorPayload(error)) {
+      throw new ConversionError(error);
+    }
+    throw error;
+  }
+}
+
+export async function listPresets(): Promise<Preset[]> {
+  return invokePresetCommand<Preset[]>('list_presets');
+}
+
+export async function savePreset(preset: Preset): Promise<Preset[]> {
+  return invokePresetCommand<Preset[]>('save_preset', { preset });
+}
+
+export async function deletePreset(name: string): Promise<boolean> {
+  return invokePresetCommand<boolean>('delete_preset', { name });
+}
+
+export async function importPresets(path: string): Promise<Preset[]> {
+  return invokePresetCommand<Preset[]>('import_presets', { path });
+}
+
+export async function exportPresets(path: string, names?: string[]): Promise<number> {
+  return invokePresetCommand<number>('export_presets', { path, names: names ?? null });
+}
+
+export async function onQueueEvent(
+  event: 'job-queued' | 'job-started' | 'job-finished',
+  handler: (payload: QueueEvent) => void
+): Promise<() => void> {
+  return listen<QueueEvent>(event, (e) => handler(e.payload));
+}
@@ -1,5 +1,5 @@
 import { useMemo, useState, useEffect, useRef } from 'react';
-import { convertWebPToMp4 } from '@/features/converter/api/convert';
+import { cancelConversion, convertWebPToMp4, ConversionError } from '@/features/converter/api/convert';
 import { Button } from '@/components/ui/button';
 import { Upload, CheckCircle2, AlertCircle, Play, Trash2, FileVideo, Folder } from 'lucide-react';
 import { open as openDialog } from '@tauri-apps/plugin-dialog';
@@ -29,11 +29,12 @@ type JobItem = {
   status: JobStatus;
   progress: number;
   error?: string;
+  logPath?: string | null;
   outputPath?: string;
   options: JobOptions;
 };
 
-type OutputFormat = 'mp4' | 'mov';
+type OutputFormat = 'mp4' | 'mov' | 'gif' | 'webm';
 
 type BatchSettings = {
   outputDir: string | null;
@@ -44,7 +45,6 @@ type BatchSettings = {
   staticDuration: number;
 };
 
-const MAX_CONCURRENT = 2;
 const DEFAULT_OPTIONS: JobOptions = {
   quality: 'high',
   fps: null,
@@ -71,6 +71,8 @@ const FPS_OPTIONS: { value: number | null; label: string }[] = [
 const FORMAT_OPTIONS: { value: OutputFormat; label: string }[] = [
   { value: 'mp4', label: 'MP4 (H.264)' },
   { value: 'mov', label: 'MOV (H.264)' },
+  { value: 'webm', label: 'WebM (VP9)' },
+  { value: 'gif', label: 'GIF (animated)' },
 ];
 const TIP_URL = 'https://ko-fi.com/pantherandcub';
 
@@ -81,12 +83,6 @@ export function VideoConverter() {
   const [showSettings, setShowSettings] = useState(false);
   const [batchSettings, setBatchSettings] = useState<BatchSettings>(DEFAULT_BATCH_SETTINGS);
   const nextSequence = useRef(1);
-  const jobsRef = useRef<JobItem[]>([]);
-
-  useEffect(() => {
-    jobsRef.current = jobs;
-  }, [jobs]);
-
   // Set up Tauri file drop listener
   useEffect(() => {
     let unlistenDrop: (() => void) | undefined;
@@ -209,18 +205,12 @@ export function VideoConverter() {
     );
   };
 
-  const extractLogPath = (message?: string) => {
-    if (!message) return null;
-    const match = message.match(/Log:\s*(.+)$/m);
-    return match ? match[1].trim() : null;
-  };
-
   const handleConvertJob = async (job: JobItem) => {
     if (job.status === 'converting') return;
-    updateJob(job.id, { status: 'converting', progress: 0, error: undefined, outputPath: undefined });
+    updateJob(job.id, { status: 'converting', progress: 0, error: undefined, logPath: undefined, outputPath: undefined });
 
     try {
-      const outputPath = await convertWebPToMp4(
+      const { outputPath } = await convertWebPToMp4(
         job.path,
         job.id,
         {
@@ -241,7 +231,8 @@ export function VideoConverter() {
     } catch (error) {
       console.error('Conversion error:', error);
       const message = error instanceof Error ? error.message : 'Conversion failed';
-      updateJob(job.id, { status: 'error', error: message });
+      const logPath = error instanceof ConversionError ? error.logPath : null;
+      updateJob(job.id, { status: 'error', error: message, logPath });
     }
   };
 
@@ -251,25 +242,8 @@ export function VideoConverter() {
     if (!queue.length) return;
 
     setBatchRunning(true);
-    const ids = queue.map(job => job.id);
-    const queueIds = [...ids];
-
-    const runNext = async (): Promise<void> => {
-      const id = queueIds.shift();
-      if (!id) return;
-      const current = jobsRef.current.find(job => job.id === id);
-      if (current && (current.status === 'idle' || current.status === 'error')) {
-        await handleConvertJob(current);
-      }
-      await runNext();
-    };
-
-    const workers = Array.from(
-      { length: Math.min(MAX_CONCURRENT, queueIds.length) },
-      () => runNext()
-    );
-
-    await Promise.all(workers);
+    // Every job goes to the backend queue at once; it runs them within its parallel limit.
+    await Promise.all(queue.map(job => handleConvertJob(job)));
     setBatchRunning(false);
   };
 
@@ -278,6 +252,10 @@ export function VideoConverter() {
   };
 
   const handleRemoveJob = (jobId: string) => {
+    const job = jobs.find(item => item.id === jobId);
+    if (job?.status === 'converting') {
+      void cancelConversion(jobId);
+    }
     setJobs(prev => prev.filter(job => job.id !== jobId));
   };
 
@@ -408,7 +386,7 @@ export function VideoConverter() {
       size: 160,
       cell: ({ row }) => {
         const job = row.original;
-        const logPath = extractLogPath(job.error);
+        const logPath = job.logPath;
         return (
           <div className="flex w-full items-center gap-1">
             <Button
@@ -444,7 +422,6 @@ export function VideoConverter() {
               variant="ghost"
               size="sm"
               onClick={() => handleRemoveJob(job.id)}
-              disabled={job.status === 'converting'}
               className="ml-auto h-7 w-7 rounded-full p-0 text-gray-500 hover:bg-black/5 dark:text-neutral-300 dark:hover:bg-white/10"
             >
               <Trash2 className="h-4 w-4" />